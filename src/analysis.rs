@@ -0,0 +1,243 @@
+use crate::gd::RawLinesTriplet;
+use crate::music::{BeatRate, Subdivision, Swing, TimeSignature};
+use crate::Color;
+use std::fmt;
+use std::time::Duration;
+
+/// The tempo map lines are expected to sit on
+#[derive(Clone, Copy)]
+pub struct Grid<'a> {
+    pub beat_rate: &'a BeatRate,
+    pub time_signatures: &'a TimeSignature,
+    pub swing: &'a Swing,
+    pub subdivision: Subdivision,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    /// Density is counted per section of this length, from the start of the song
+    pub section_length: Duration,
+    /// Width of one bar of the interval histogram
+    pub histogram_bucket: Duration,
+    /// Longer intervals all land in the histogram's last bar
+    pub histogram_max: Duration,
+    /// How far from the grid a line may be before it's off-grid
+    pub grid_tolerance: Duration,
+    /// Lines closer together than this are duplicates, whatever their colour
+    pub duplicate_tolerance: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Line {
+    pub color: Color,
+    pub time: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub counts: [(Color, usize); 3],
+    /// Lines in each `section_length` of the song
+    pub sections: Vec<usize>,
+    /// Intervals between consecutive lines, in `histogram_bucket`s
+    pub intervals: Vec<usize>,
+    /// Lines away from the grid, with how many ms late (negative: early) they are
+    pub off_grid: Vec<(Line, f64)>,
+    pub duplicates: Vec<(Line, Line)>,
+    pub past_end: Vec<Line>,
+    settings: Settings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            section_length: Duration::from_secs(10),
+            histogram_bucket: Duration::from_millis(50),
+            histogram_max: Duration::from_secs(2),
+            grid_tolerance: Duration::from_millis(5),
+            duplicate_tolerance: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Looks for sloppy guidelines; without a grid nothing is off-grid, and without a song length
+/// nothing is past the end
+pub fn analyze(
+    lines: &RawLinesTriplet,
+    grid: Option<Grid>,
+    song_length: Option<Duration>,
+    settings: Settings,
+) -> Report {
+    let mut all: Vec<Line> = [(Color::Green, &lines.green), (Color::Yellow, &lines.yellow), (Color::Orange, &lines.orange)]
+        .into_iter()
+        .flat_map(|(color, lines)| lines.get_positions().iter().map(move |&time| Line { color, time }))
+        .collect();
+    all.sort_by_key(|line| line.time);
+
+    let counts = [
+        (Color::Green, lines.green.get_positions().len()),
+        (Color::Yellow, lines.yellow.get_positions().len()),
+        (Color::Orange, lines.orange.get_positions().len()),
+    ];
+
+    let end = song_length.into_iter().chain(all.last().map(|line| line.time)).max().unwrap_or_default();
+    let section_count = (end.as_secs_f64() / settings.section_length.as_secs_f64()).floor() as usize + 1;
+    let mut sections = vec![0; if all.is_empty() { 0 } else { section_count }];
+    for line in &all {
+        let section = (line.time.as_secs_f64() / settings.section_length.as_secs_f64()) as usize;
+        sections[section.min(section_count - 1)] += 1;
+    }
+
+    let buckets = (settings.histogram_max.as_secs_f64() / settings.histogram_bucket.as_secs_f64()).ceil() as usize;
+    let mut intervals = vec![0; buckets + 1];
+    let mut duplicates = Vec::new();
+    for pair in all.windows(2) {
+        let interval = pair[1].time - pair[0].time;
+        if interval < settings.duplicate_tolerance {
+            duplicates.push((pair[0], pair[1]));
+            continue;
+        }
+        let bucket = (interval.as_secs_f64() / settings.histogram_bucket.as_secs_f64()) as usize;
+        intervals[bucket.min(buckets)] += 1;
+    }
+
+    let off_grid = match grid {
+        Some(grid) => all
+            .iter()
+            .filter_map(|&line| {
+                let pos = grid.beat_rate.beat_at(line.time);
+                let snapped = grid.swing.snap(pos, grid.subdivision, grid.time_signatures);
+                let deviation = line.time.as_secs_f64() - grid.beat_rate.time_at(snapped).as_secs_f64();
+                (deviation.abs() > settings.grid_tolerance.as_secs_f64()).then_some((line, deviation * 1000.0))
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let past_end = match song_length {
+        Some(length) => all.iter().copied().filter(|line| line.time > length).collect(),
+        None => Vec::new(),
+    };
+
+    Report { counts, sections, intervals, off_grid, duplicates, past_end, settings }
+}
+
+impl Report {
+    /// True if nothing needs a second look
+    pub fn clean(&self) -> bool {
+        self.off_grid.is_empty() && self.duplicates.is_empty() && self.past_end.is_empty()
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at {:.3}s", self.color, self.time.as_secs_f64())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Settings { section_length, histogram_bucket, .. } = self.settings;
+        let counts = self.counts.iter().map(|(color, count)| format!("{count} {color:?}")).collect::<Vec<_>>();
+        writeln!(f, "Lines: {}", counts.join(", "))?;
+
+        writeln!(f, "\nLines per {}s:", section_length.as_secs_f64())?;
+        for (idx, count) in self.sections.iter().enumerate() {
+            let start = section_length.as_secs_f64() * idx as f64;
+            writeln!(f, "{start:>8.1}s {count:>4} {}", "#".repeat(*count))?;
+        }
+
+        writeln!(f, "\nIntervals:")?;
+        let last = self.intervals.len().saturating_sub(1);
+        for (idx, &count) in self.intervals.iter().enumerate().filter(|&(_, &count)| count > 0) {
+            let from = histogram_bucket.as_millis() * idx as u128;
+            let range = if idx == last {
+                format!("{from}ms+")
+            } else {
+                format!("{from}-{}ms", from + histogram_bucket.as_millis())
+            };
+            writeln!(f, "{range:>12} {count:>4} {}", "#".repeat(count))?;
+        }
+
+        if !self.off_grid.is_empty() {
+            writeln!(f, "\nOff the grid:")?;
+            for (line, deviation) in &self.off_grid {
+                writeln!(f, "  {line}: {deviation:+.1}ms")?;
+            }
+        }
+        if !self.duplicates.is_empty() {
+            writeln!(f, "\nDuplicates:")?;
+            for (first, second) in &self.duplicates {
+                writeln!(f, "  {first} and {second}")?;
+            }
+        }
+        if !self.past_end.is_empty() {
+            writeln!(f, "\nPast the end of the song:")?;
+            for line in &self.past_end {
+                writeln!(f, "  {line}")?;
+            }
+        }
+        if self.clean() {
+            writeln!(f, "\nNothing to fix")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{StaticBeatRate, StaticTimeSignature};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn lines() -> RawLinesTriplet {
+        let mut lines = RawLinesTriplet::default();
+        for time in [0, 500, 1000, 1503, 2000] {
+            lines.green.insert(ms(time));
+        }
+        lines.orange.insert(ms(1006));
+        lines.yellow.insert(ms(12_000));
+        lines
+    }
+
+    #[test]
+    fn counts_and_sections() {
+        let report = analyze(&lines(), None, Some(ms(15_000)), Settings::default());
+        assert_eq!(report.counts, [(Color::Green, 5), (Color::Yellow, 1), (Color::Orange, 1)]);
+        assert_eq!(report.sections, [6, 1]);
+        assert!(report.off_grid.is_empty());
+        assert!(report.past_end.is_empty());
+    }
+
+    #[test]
+    fn intervals_and_duplicates() {
+        let report = analyze(&lines(), None, None, Settings::default());
+        let duplicate = (Line { color: Color::Green, time: ms(1000) }, Line { color: Color::Orange, time: ms(1006) });
+        assert_eq!(report.duplicates, [duplicate]);
+        // 500, 500, 497, 497 and 10000ms; the duplicate doesn't count
+        assert_eq!(report.intervals[9], 2);
+        assert_eq!(report.intervals[10], 2);
+        assert_eq!(report.intervals[40], 1);
+        assert_eq!(report.intervals.iter().sum::<usize>(), 5);
+    }
+
+    #[test]
+    fn off_grid_and_past_end() {
+        let beat_rate = StaticBeatRate::from_bpm(120.0).into();
+        let time_signatures = StaticTimeSignature::new(4, 4).into();
+        let swing = Swing::default();
+        let grid = Grid {
+            beat_rate: &beat_rate,
+            time_signatures: &time_signatures,
+            swing: &swing,
+            subdivision: Subdivision::straight(4),
+        };
+        let report = analyze(&lines(), Some(grid), Some(ms(10_000)), Settings::default());
+        let off: Vec<_> = report.off_grid.iter().map(|(line, deviation)| (line.time, deviation.round())).collect();
+        assert_eq!(off, [(ms(1006), 6.0)]);
+        assert_eq!(report.past_end, [Line { color: Color::Yellow, time: ms(12_000) }]);
+        assert!(!report.clean());
+    }
+}
@@ -0,0 +1,423 @@
+use rodio::source::{Buffered, SkipDuration};
+use rodio::{Decoder, Sample, Source};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::time::Duration;
+use thiserror::Error;
+
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Length of one analysis window
+const WINDOW: Duration = Duration::from_millis(40);
+/// How far a window may be shifted from its ideal position to line up with the previous one
+const SEEK_TOLERANCE: Duration = Duration::from_millis(8);
+
+/// How playback at a rate other than 1x is produced
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StretchMode {
+    /// Overlap-add time stretching; the song keeps its pitch
+    #[default]
+    PreservePitch,
+    /// Plain resampling; pitch follows the playback rate
+    Resample,
+}
+
+/// Audio file formats songs can be decoded from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Mp3,
+    Vorbis,
+    Wav,
+    Flac,
+}
+
+type TrackSource = Buffered<Box<dyn Source<Item = i16> + Send>>;
+
+/// A decoded song, playable from any position; knows nothing about audio devices
+#[derive(Clone)]
+pub struct Track {
+    source: TrackSource,
+    length: Duration,
+}
+
+#[derive(Debug, Error)]
+pub enum TrackError {
+    #[error("Couldn't read song file")]
+    Io(#[from] io::Error),
+    #[error("Couldn't decode song file")]
+    Decoder(#[from] rodio::decoder::DecoderError),
+    #[error("Song file isn't mp3, ogg, wav or flac")]
+    UnknownFormat,
+    #[error("Song file contains no audio")]
+    Empty,
+}
+
+/// Changes the tempo of a source without changing its pitch (WSOLA).
+///
+/// Windows are taken from the input at `speed` times the rate they are written to the output, and
+/// each one is nudged within `SEEK_TOLERANCE` to where it best continues the previous window.
+pub struct TimeStretch<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    input: S,
+    channels: usize,
+    sample_rate: u32,
+    speed: f32,
+    window: Vec<f32>,
+    tolerance: usize,
+    /// interleaved input samples, the first of which belongs to frame `buffer_start`
+    buffer: VecDeque<f32>,
+    buffer_start: usize,
+    input_done: bool,
+    /// ideal input frame for the next window
+    analysis_pos: f64,
+    /// input frame that would seamlessly follow the previous window
+    natural_next: Option<usize>,
+    /// second half of the previous window, still to be overlapped
+    tail: Vec<f32>,
+    output: VecDeque<f32>,
+    finished: bool,
+}
+
+impl StretchMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::PreservePitch => "Preserve pitch",
+            Self::Resample => "Resample",
+        }
+    }
+}
+
+impl Format {
+    /// Bytes `sniff` needs to tell the formats apart
+    pub const HEADER_LEN: usize = 12;
+
+    /// Recognizes a format by its first bytes, since file extensions can't be trusted
+    pub fn sniff(header: &[u8]) -> Option<Self> {
+        match header {
+            [b'O', b'g', b'g', b'S', ..] => Some(Self::Vorbis),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(Self::Wav),
+            [b'f', b'L', b'a', b'C', ..] => Some(Self::Flac),
+            [b'I', b'D', b'3', ..] => Some(Self::Mp3),
+            // bare mpeg frame sync
+            [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some(Self::Mp3),
+            _ => None,
+        }
+    }
+}
+
+impl Track {
+    /// Decodes `file`, in which the track starts `offset` seconds in; a negative offset pads
+    /// the start with silence instead
+    pub fn open(mut file: File, offset: f64) -> Result<Self, TrackError> {
+        let format = sniff(&mut file)?;
+        let file_length = measure(&mut file, format)?;
+        Ok(Self::from_source(decode(file, format)?, file_length, offset))
+    }
+
+    pub fn from_source<S>(source: S, source_length: Duration, offset: f64) -> Self
+    where
+        S: Source<Item = i16> + Send + 'static,
+    {
+        let (source, length): (Box<dyn Source<Item = i16> + Send>, _) = if offset >= 0.0 {
+            let skipped = Duration::from_secs_f64(offset);
+            (Box::new(source.skip_duration(skipped)), source_length.saturating_sub(skipped))
+        } else {
+            let delay = Duration::from_secs_f64(-offset);
+            (Box::new(source.delay(delay)), source_length + delay)
+        };
+        Self { source: source.buffered(), length }
+    }
+
+    pub fn length(&self) -> Duration {
+        self.length
+    }
+
+    /// The track from `position` on
+    pub fn source_from(&self, position: Duration) -> SkipDuration<TrackSource> {
+        self.source.clone().skip_duration(position)
+    }
+}
+
+/// Reads the file's header to find its format, then rewinds it
+fn sniff(file: &mut File) -> Result<Format, TrackError> {
+    let mut header = Vec::with_capacity(Format::HEADER_LEN);
+    file.by_ref().take(Format::HEADER_LEN as u64).read_to_end(&mut header)?;
+    file.rewind()?;
+    Format::sniff(&header).ok_or(TrackError::UnknownFormat)
+}
+
+fn decode(file: File, format: Format) -> Result<Decoder<File>, TrackError> {
+    Ok(match format {
+        Format::Mp3 => Decoder::new_mp3(file)?,
+        Format::Vorbis => Decoder::new_vorbis(file)?,
+        Format::Wav => Decoder::new_wav(file)?,
+        Format::Flac => Decoder::new_flac(file)?,
+    })
+}
+
+/// Length of the whole file. Mp3s rarely state it, so their frames are scanned; anything
+/// else that doesn't state it is decoded once in full. Leaves the file rewound.
+fn measure(file: &mut File, format: Format) -> Result<Duration, TrackError> {
+    let stated = match format {
+        Format::Mp3 => mp3_duration::from_read(&mut BufReader::new(&mut *file))
+            .map_err(|e| log::info!("Couldn't scan mp3 for its length: {e}"))
+            .ok(),
+        _ => decode(file.try_clone()?, format)?.total_duration(),
+    };
+    file.rewind()?;
+    if let Some(length) = stated {
+        return Ok(length);
+    }
+
+    let decoder = decode(file.try_clone()?, format)?;
+    let samples_per_sec = decoder.channels() as f64 * decoder.sample_rate() as f64;
+    let samples = decoder.count();
+    file.rewind()?;
+    if samples == 0 || samples_per_sec == 0.0 {
+        return Err(TrackError::Empty);
+    }
+    Ok(Duration::from_secs_f64(samples as f64 / samples_per_sec))
+}
+
+impl<S> TimeStretch<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    pub fn new(input: S, speed: f32) -> Self {
+        let channels = input.channels().max(1) as usize;
+        let sample_rate = input.sample_rate();
+        let frames_in = |dur: Duration| (dur.as_secs_f64() * sample_rate as f64) as usize;
+        // even length so the two halves of a window line up exactly
+        let window_len = (frames_in(WINDOW) & !1).max(2);
+        let window = (0..window_len)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / window_len as f32).cos())
+            .collect();
+
+        Self {
+            input,
+            channels,
+            sample_rate,
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+            window,
+            tolerance: frames_in(SEEK_TOLERANCE),
+            buffer: VecDeque::new(),
+            buffer_start: 0,
+            input_done: false,
+            analysis_pos: 0.0,
+            natural_next: None,
+            tail: vec![0.0; window_len / 2 * channels],
+            output: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    fn hop(&self) -> usize {
+        self.window.len() / 2
+    }
+
+    fn buffered_frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    /// Reads input until frame `end` (exclusive) is buffered or the input runs out
+    fn fill_until(&mut self, end: usize) {
+        while !self.input_done && self.buffer_start + self.buffered_frames() < end {
+            match self.input.next() {
+                Some(sample) => self.buffer.push_back(sample.to_f32()),
+                None => self.input_done = true,
+            }
+        }
+    }
+
+    /// Sample of `channel` at input frame `frame`; silence outside of the buffered input
+    fn sample(&self, frame: usize, channel: usize) -> f32 {
+        frame
+            .checked_sub(self.buffer_start)
+            .and_then(|offset| self.buffer.get(offset * self.channels + channel))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    fn mono(&self, frame: usize) -> f32 {
+        (0..self.channels).map(|ch| self.sample(frame, ch)).sum()
+    }
+
+    /// Start frame within the tolerance around `target` that best continues `natural`
+    fn best_start(&self, target: usize, natural: usize) -> usize {
+        let hop = self.hop();
+        let lowest = target.saturating_sub(self.tolerance).max(self.buffer_start);
+        let highest = target + self.tolerance;
+        let reference: Vec<f32> = (0..hop).step_by(2).map(|i| self.mono(natural + i)).collect();
+        (lowest..=highest)
+            .map(|start| {
+                let (correlation, energy) = reference.iter().zip((0..hop).step_by(2)).fold(
+                    (0.0, 0.0),
+                    |(correlation, energy), (r, i)| {
+                        let candidate = self.mono(start + i);
+                        (correlation + r * candidate, energy + candidate * candidate)
+                    },
+                );
+                (start, correlation / energy.sqrt().max(f32::EPSILON))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(target, |(start, _)| start)
+    }
+
+    /// Overlap-adds one more window, producing `hop` frames of output
+    fn step(&mut self) {
+        let hop = self.hop();
+        let target = self.analysis_pos.round() as usize;
+        self.fill_until(target + self.tolerance + self.window.len());
+
+        if self.input_done && target >= self.buffer_start + self.buffered_frames() {
+            self.output.extend(self.tail.drain(..));
+            self.finished = true;
+            return;
+        }
+
+        let start = match self.natural_next {
+            Some(natural) => self.best_start(target, natural),
+            None => target,
+        };
+
+        let mut segment = Vec::with_capacity(self.window.len() * self.channels);
+        for (i, coefficient) in self.window.iter().enumerate() {
+            for ch in 0..self.channels {
+                segment.push(self.sample(start + i, ch) * coefficient);
+            }
+        }
+        let (first_half, second_half) = segment.split_at(hop * self.channels);
+        self.output
+            .extend(first_half.iter().zip(&self.tail).map(|(new, old)| new + old));
+        self.tail = second_half.to_vec();
+
+        self.natural_next = Some(start + hop);
+        self.analysis_pos += hop as f64 * self.speed as f64;
+
+        let keep_from = (self.analysis_pos as usize)
+            .saturating_sub(self.tolerance)
+            .min(start + hop);
+        while self.buffer_start < keep_from && !self.buffer.is_empty() {
+            self.buffer.drain(..self.channels.min(self.buffer.len()));
+            self.buffer_start += 1;
+        }
+    }
+}
+
+impl<S> Iterator for TimeStretch<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        while self.output.is_empty() && !self.finished {
+            self.step();
+        }
+        self.output.pop_front()
+    }
+}
+
+impl<S> Source for TimeStretch<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input
+            .total_duration()
+            .map(|dur| dur.div_f32(self.speed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn sine(seconds: f32) -> SamplesBuffer<f32> {
+        let rate = 8000;
+        let samples = (0..(seconds * rate as f32) as usize)
+            .map(|n| (2.0 * PI * 440.0 * n as f32 / rate as f32).sin())
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(1, rate, samples)
+    }
+
+    #[test]
+    fn half_speed_doubles_length() {
+        let stretched = TimeStretch::new(sine(1.0), 0.5).count() as f32;
+        assert!((stretched / 16000.0 - 1.0).abs() < 0.02, "{stretched}");
+    }
+
+    #[test]
+    fn double_speed_halves_length() {
+        let stretched = TimeStretch::new(sine(1.0), 2.0).count() as f32;
+        assert!((stretched / 4000.0 - 1.0).abs() < 0.05, "{stretched}");
+    }
+
+    #[test]
+    fn unit_speed_keeps_signal() {
+        let original: Vec<f32> = sine(0.5).collect();
+        let stretched: Vec<f32> = TimeStretch::new(sine(0.5), 1.0).collect();
+        // skip the fade-in of the very first window
+        original
+            .iter()
+            .zip(&stretched)
+            .skip(400)
+            .take(3000)
+            .for_each(|(a, b)| assert!((a - b).abs() < 1e-3, "{a} != {b}"));
+    }
+
+    #[test]
+    fn sniffs_formats() {
+        assert_eq!(Format::sniff(b"ID3\x04\x00\x00\x00\x00\x00\x00\x00\x00"), Some(Format::Mp3));
+        assert_eq!(Format::sniff(&[0xFF, 0xFB, 0x90, 0x64]), Some(Format::Mp3));
+        assert_eq!(Format::sniff(b"OggS\x00\x02"), Some(Format::Vorbis));
+        assert_eq!(Format::sniff(b"RIFF\x24\x08\x00\x00WAVEfmt "), Some(Format::Wav));
+        assert_eq!(Format::sniff(b"fLaC\x00\x00\x00\x22"), Some(Format::Flac));
+        assert_eq!(Format::sniff(b"RIFF\x24\x08\x00\x00AVI "), None);
+        assert_eq!(Format::sniff(b"<html>"), None);
+        assert_eq!(Format::sniff(b""), None);
+    }
+
+    #[test]
+    fn track_offset_skips_or_pads() {
+        let source = || SamplesBuffer::new(1, 1000, vec![1i16; 2000]);
+        let skipped = Track::from_source(source(), Duration::from_secs(2), 0.5);
+        assert_eq!(skipped.length(), Duration::from_millis(1500));
+        assert_eq!(skipped.source_from(Duration::ZERO).count(), 1500);
+
+        let padded = Track::from_source(source(), Duration::from_secs(2), -0.5);
+        assert_eq!(padded.length(), Duration::from_millis(2500));
+        let samples: Vec<i16> = padded.source_from(Duration::ZERO).collect();
+        assert_eq!(samples.len(), 2500);
+        assert!(samples[..500].iter().all(|&s| s == 0));
+        assert!(samples[500..].iter().all(|&s| s == 1));
+    }
+
+    #[test]
+    fn speed_is_clamped() {
+        assert_eq!(TimeStretch::new(sine(0.1), 10.0).speed, MAX_SPEED);
+        assert_eq!(TimeStretch::new(sine(0.1), 0.0).speed, MIN_SPEED);
+    }
+}
@@ -0,0 +1,356 @@
+use crate::music::Subdivision;
+use crate::{analysis, audio, config, fetch, gd, gmd, labels, project, GdlData, SongError};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+const USAGE: &str = "\
+Usage: pipedash [--save <CCLocalLevels.dat>] <command>
+
+Commands:
+  list                           Levels in the save, with the index to pick them by
+  show-lines <level>             Guidelines as Audacity labels (seconds, seconds, colour)
+  export <level> <file>          Guidelines to an Audacity label file, or the level to a .gmd
+  import <level> <file> [--merge]
+                                 Guidelines from an Audacity label file or a .gmd into the save
+  generate <level> --bpm <bpm> [--offset <s>] [--length <s>] [--color <colour>] [--merge]
+                                 A guideline on every beat, up to the end of the song by default
+  report <level> [--length <s>] [--subdivision <note>]
+                                 Counts, density, intervals and sloppy guidelines; the grid comes
+                                 from the level's Pipedash project, if it has one, split into
+                                 <note>ths (16 by default; 8t for eighth triplets, 8d dotted,
+                                 8q quintuplets)
+
+<level> is an index from `list` or a level name. Without a command, the editor starts.";
+
+/// Options that take a value; everything else starting with `--` is a flag
+const VALUE_OPTIONS: [&str; 7] = ["save", "bpm", "offset", "length", "color", "colour", "subdivision"];
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("{0}")]
+    Usage(String),
+    #[error("No level matches {0:?}")]
+    UnknownLevel(String),
+    #[error("Level {0:?} has no level data")]
+    NoLevelData(String),
+    #[error("Level {0:?} isn't in the save any more")]
+    LevelNotInSave(String),
+    #[error("{0:?} isn't a colour (green, yellow or orange)")]
+    UnknownColor(String),
+    #[error("Couldn't find the song's length ({0}), pass --length")]
+    UnknownLength(SongError),
+    #[error("Some guidelines need fixing")]
+    NeedsFixing,
+    #[error("Couldn't access {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error(transparent)]
+    Save(#[from] gd::SaveError),
+    #[error(transparent)]
+    Labels(#[from] labels::LabelError),
+    #[error(transparent)]
+    Gmd(#[from] gmd::GmdError),
+}
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if VALUE_OPTIONS.contains(&name) => {
+                    let value = args.next().ok_or_else(|| CliError::Usage(format!("--{name} needs a value")))?;
+                    options.insert(name.to_owned(), value.clone());
+                }
+                Some(name) => {
+                    options.insert(name.to_owned(), String::new());
+                }
+                None => positional.push(arg.clone()),
+            }
+        }
+        Ok(Self { positional, options })
+    }
+
+    fn positional(&self, idx: usize, name: &str) -> Result<&str, CliError> {
+        self.positional
+            .get(idx)
+            .map(String::as_str)
+            .ok_or_else(|| CliError::Usage(format!("Missing <{name}>")))
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn number(&self, name: &str) -> Result<Option<f64>, CliError> {
+        self.options
+            .get(name)
+            .map(|value| {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| CliError::Usage(format!("--{name} takes a number, not {value:?}")))
+            })
+            .transpose()
+    }
+
+    /// A note value like `16`, with `t`, `q` or `d` after it for triplets, quintuplets or dotted notes
+    fn subdivision(&self, name: &str) -> Result<Option<Subdivision>, CliError> {
+        self.options
+            .get(name)
+            .map(|value| {
+                let (note, kind): (&str, fn(u32) -> Subdivision) = match value.as_bytes().last() {
+                    Some(b't') => (&value[..value.len() - 1], Subdivision::triplet),
+                    Some(b'q') => (&value[..value.len() - 1], Subdivision::quintuplet),
+                    Some(b'd') => (&value[..value.len() - 1], Subdivision::dotted),
+                    _ => (value.as_str(), Subdivision::straight),
+                };
+                note.parse::<u32>()
+                    .ok()
+                    .filter(|&note| note > 0)
+                    .map(kind)
+                    .ok_or_else(|| CliError::Usage(format!("--{name} takes a note value like 16 or 8t, not {value:?}")))
+            })
+            .transpose()
+    }
+}
+
+/// Runs the command line (without the program name) and returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    match Args::parse(args).and_then(|args| execute(&args)) {
+        Ok(()) => 0,
+        Err(e @ CliError::Usage(_)) => {
+            eprintln!("{e}\n\n{USAGE}");
+            2
+        }
+        Err(e) => {
+            log::error!("{e}");
+            eprintln!("Error: {e}");
+            1
+        }
+    }
+}
+
+fn execute(args: &Args) -> Result<(), CliError> {
+    let save_path = args.options.get("save").map_or_else(gd::LocalLevels::default_path, PathBuf::from);
+    if args.flag("help") || matches!(args.positional.first().map(String::as_str), Some("help" | "-h")) {
+        println!("{USAGE}");
+        return Ok(());
+    }
+    let command = args.positional(0, "command")?;
+    let mut save = gd::LocalLevels::open(&save_path)?;
+    let levels = save.levels();
+
+    match command {
+        "list" => {
+            for (idx, level) in levels.iter().enumerate() {
+                let revision = level.revision().map(|rev| format!(" (rev {rev})")).unwrap_or_default();
+                let song = match level.song() {
+                    gd::Song::Official { id } => gd::OfficialSong::from_id(id)
+                        .map_or_else(|| format!("official song {id}"), |song| song.name.to_owned()),
+                    gd::Song::Newgrounds { id } => format!("Newgrounds {id}"),
+                    gd::Song::Unknown => "unknown song".into(),
+                };
+                println!("{idx}\t{}{revision}\t{song}", level.name());
+            }
+        }
+        "show-lines" => {
+            let level = find_level(&levels, args.positional(1, "level")?)?;
+            print!("{}", labels::export(&inner(level)?.get_lines()));
+        }
+        "export" => {
+            let level = find_level(&levels, args.positional(1, "level")?)?;
+            let path = Path::new(args.positional(2, "file")?);
+            let inner = inner(level)?;
+            if is_gmd(path) {
+                gmd::export(path, level, &inner)?;
+            } else {
+                let text = labels::export(&inner.get_lines());
+                fs::write(path, text).map_err(|e| CliError::Io(path.into(), e))?;
+            }
+        }
+        "import" => {
+            let level = find_level(&levels, args.positional(1, "level")?)?;
+            let path = Path::new(args.positional(2, "file")?);
+            let lines = if is_gmd(path) {
+                let shared = gmd::import(path)?;
+                inner(&shared)?.get_lines()
+            } else {
+                let text = fs::read_to_string(path).map_err(|e| CliError::Io(path.into(), e))?;
+                labels::import(&text)?
+            };
+            write_lines(&mut save, level, lines, args.flag("merge"))?;
+        }
+        "generate" => {
+            let level = find_level(&levels, args.positional(1, "level")?)?;
+            let bpm = args
+                .number("bpm")?
+                .filter(|&bpm| bpm > 0.0)
+                .ok_or_else(|| CliError::Usage("generate needs a positive --bpm".into()))?;
+            let offset = args.number("offset")?.unwrap_or(0.0);
+            let length = match args.number("length")? {
+                Some(length) => length,
+                None => song_length(level)?.as_secs_f64(),
+            };
+            let color = args.options.get("color").or_else(|| args.options.get("colour"));
+            let color = color.map_or("green", String::as_str);
+
+            let mut lines = gd::RawLinesTriplet::default();
+            let target = labels::lines_for(&mut lines, color).ok_or_else(|| CliError::UnknownColor(color.into()))?;
+            let beat = 60.0 / bpm;
+            // counting beats rather than adding them up keeps rounding errors from piling up
+            let first = (-offset / beat).ceil().max(0.0) as u64;
+            for time in (first..).map(|n| offset + n as f64 * beat).take_while(|&time| time < length) {
+                target.insert(Duration::from_secs_f64(time.max(0.0)));
+            }
+            write_lines(&mut save, level, lines, args.flag("merge"))?;
+        }
+        "report" => {
+            let level = find_level(&levels, args.positional(1, "level")?)?;
+            let lines = inner(level)?.get_lines();
+            let length = match args.number("length")? {
+                Some(length) => Some(Duration::from_secs_f64(length.max(0.0))),
+                None => song_length(level)
+                    .map_err(|e| log::warn!("Not checking for lines past the end: {e}"))
+                    .ok(),
+            };
+            let subdivision = args.subdivision("subdivision")?.unwrap_or(Subdivision::straight(16));
+            let project = project::load(&level.into()).unwrap_or_else(|e| {
+                log::warn!("Not checking against the grid: {e}");
+                None
+            });
+            let grid = project.as_ref().map(|data| report_grid(data, subdivision));
+            let report = analysis::analyze(&lines, grid, length, Default::default());
+            print!("{report}");
+            if !report.clean() {
+                return Err(CliError::NeedsFixing);
+            }
+        }
+        other => return Err(CliError::Usage(format!("Unknown command {other:?}"))),
+    }
+    Ok(())
+}
+
+fn find_level<'a>(levels: &'a [gd::Level], query: &str) -> Result<&'a gd::Level, CliError> {
+    query
+        .parse::<usize>()
+        .ok()
+        .and_then(|idx| levels.get(idx))
+        .or_else(|| levels.iter().find(|level| level.name() == query))
+        .ok_or_else(|| CliError::UnknownLevel(query.into()))
+}
+
+fn report_grid(data: &GdlData, subdivision: Subdivision) -> analysis::Grid {
+    analysis::Grid {
+        beat_rate: &data.beat_rate,
+        time_signatures: &data.time_signatures,
+        swing: &data.swing,
+        subdivision,
+    }
+}
+
+fn inner(level: &gd::Level) -> Result<gd::InnerLevel, CliError> {
+    level.inner().ok_or_else(|| CliError::NoLevelData(level.name().into()))
+}
+
+fn is_gmd(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("gmd"))
+}
+
+/// Puts `lines` into the level's guidelines and writes the save back
+fn write_lines(
+    save: &mut gd::LocalLevels,
+    level: &gd::Level,
+    mut lines: gd::RawLinesTriplet,
+    merge: bool,
+) -> Result<(), CliError> {
+    let inner = inner(level)?;
+    if merge {
+        lines.merge(inner.get_lines());
+    }
+    let count = [&lines.orange, &lines.yellow, &lines.green]
+        .into_iter()
+        .map(|lines| lines.get_positions().len())
+        .sum::<usize>();
+    if !save.set_inner(level, &inner.with_lines(&lines)) {
+        return Err(CliError::LevelNotInSave(level.name().into()));
+    }
+    save.write()?;
+    println!("Wrote {count} guidelines to {}", level.name());
+    Ok(())
+}
+
+/// Length of the level's song (or the project's custom audio), fetching it if need be
+fn song_length(level: &gd::Level) -> Result<Duration, CliError> {
+    let custom_song = project::load(&level.into()).ok().flatten().and_then(|data| data.custom_song);
+    let song_file = fetch::open_now(&level.song(), custom_song.as_ref(), &config::Config::load())
+        .map_err(CliError::UnknownLength)?;
+    let offset = song_file.custom_offset.unwrap_or(0.0);
+    audio::Track::open(song_file.file, offset)
+        .map(|track| track.length())
+        .map_err(|e| CliError::UnknownLength(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, CliError> {
+        Args::parse(&args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_options_anywhere() {
+        let args = parse(&["generate", "--bpm", "140", "My Level", "--merge", "--offset", "-0.23"]).unwrap();
+        assert_eq!(args.positional, ["generate", "My Level"]);
+        assert_eq!(args.number("bpm").unwrap(), Some(140.0));
+        assert_eq!(args.number("offset").unwrap(), Some(-0.23));
+        assert_eq!(args.number("length").unwrap(), None);
+        assert!(args.flag("merge"));
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(matches!(parse(&["generate", "--bpm"]), Err(CliError::Usage(_))));
+        let args = parse(&["generate", "--bpm", "fast"]).unwrap();
+        assert!(matches!(args.number("bpm"), Err(CliError::Usage(_))));
+        assert!(matches!(args.positional(1, "level"), Err(CliError::Usage(_))));
+        for bad in ["0", "t", "eighth", "-8"] {
+            let args = parse(&["report", "--subdivision", bad]).unwrap();
+            assert!(matches!(args.subdivision("subdivision"), Err(CliError::Usage(_))), "{bad}");
+        }
+    }
+
+    #[test]
+    fn report_checks_the_chosen_subdivision() {
+        // 120 bpm in 4/4
+        let data = GdlData::default();
+        let mut lines = gd::RawLinesTriplet::default();
+        // eighths and a sixteenth
+        for ms in [0, 250, 500, 875] {
+            lines.green.insert(Duration::from_millis(ms));
+        }
+
+        let args = parse(&["report", "0", "--subdivision", "8t"]).unwrap();
+        assert_eq!(args.subdivision("subdivision").unwrap(), Some(Subdivision::triplet(8)));
+        let args = parse(&["report", "0", "--subdivision", "8"]).unwrap();
+        let eighths = args.subdivision("subdivision").unwrap().unwrap();
+        let report = analysis::analyze(&lines, Some(report_grid(&data, eighths)), None, Default::default());
+        let off: Vec<_> = report.off_grid.iter().map(|(line, _)| line.time).collect();
+        assert_eq!(off, [Duration::from_millis(875)]);
+
+        let args = parse(&["report", "0", "--subdivision", "16"]).unwrap();
+        let sixteenths = args.subdivision("subdivision").unwrap().unwrap();
+        let report = analysis::analyze(&lines, Some(report_grid(&data, sixteenths)), None, Default::default());
+        assert!(report.clean());
+    }
+}
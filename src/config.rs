@@ -0,0 +1,62 @@
+use crate::gd;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// User settings, kept in `config.ron` in the config directory
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The game's `Resources` folder, where the official songs live
+    pub resources_dir: PathBuf,
+    /// Where song info is requested from
+    pub server: gd::Server,
+    /// Off to edit without an audio device; the playhead still moves
+    pub audio_output: bool,
+    /// Seconds taps land after the moment they were meant for; taken off every tap
+    pub tap_latency: f64,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Couldn't write settings")]
+    Io(#[from] std::io::Error),
+    #[error("Couldn't serialize settings")]
+    Serialize(#[from] ron::Error),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            resources_dir: gd::resources_path(),
+            server: Default::default(),
+            audio_output: true,
+            tap_latency: 0.0,
+        }
+    }
+}
+
+impl Config {
+    fn path() -> PathBuf {
+        crate::project_dirs().config_dir().join("config.ron")
+    }
+
+    /// Reads the settings, falling back to defaults if they are missing or unreadable
+    pub fn load() -> Self {
+        match fs::read_to_string(Self::path()) {
+            Ok(contents) => ron::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!("Settings unreadable, using defaults: {e}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        fs::create_dir_all(crate::project_dirs().config_dir())?;
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(Self::path(), contents)?;
+        Ok(())
+    }
+}
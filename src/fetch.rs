@@ -0,0 +1,286 @@
+use crate::{config, gd, song_cache, CustomSong, Message, SongError};
+use reqwest::blocking as req;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+/// Bytes read from the download between progress reports
+const CHUNK_SIZE: usize = 64 * 1024;
+/// The server rounds song sizes to two decimals
+const SIZE_TOLERANCE_MB: f64 = 0.01;
+
+/// A level's song file, found (and downloaded, if need be) by a `Fetch`
+#[derive(Debug)]
+pub struct SongFile {
+    pub file: File,
+    pub name: String,
+    pub artist: String,
+    pub id: i64,
+    /// For a custom audio file, seconds into it where the level's song starts
+    pub custom_offset: Option<f64>,
+}
+
+/// Song lookup and download running on a worker thread; dropping it cancels the download.
+///
+/// The worker reports through `Message::DownloadProgress` and finishes with
+/// `Message::SongFetched`.
+pub struct Fetch {
+    receiver: mpsc::Receiver<Message>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Fetch {
+    pub fn start(gd_song: gd::Song, custom_song: Option<CustomSong>, config: &config::Config) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let config = config.clone();
+        let worker_cancel = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let progress = |done, total| {
+                // a closed channel means nobody is waiting anymore, and cancel is set anyway
+                let _ = sender.send(Message::DownloadProgress { done, total });
+            };
+            let result = match custom_song {
+                Some(custom_song) => open_custom(&gd_song, &custom_song),
+                None => fetch(&gd_song, &config, &progress, &worker_cancel),
+            };
+            if !matches!(result, Err(SongError::Cancelled)) {
+                let _ = sender.send(Message::SongFetched(result));
+            }
+        });
+        Self { receiver, cancel }
+    }
+
+    /// Messages the worker sent since the last poll
+    pub fn poll(&self) -> impl Iterator<Item = Message> + '_ {
+        self.receiver.try_iter()
+    }
+}
+
+impl Drop for Fetch {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Finds (or downloads) the song on the calling thread, for callers that can wait
+pub fn open_now(
+    gd_song: &gd::Song,
+    custom_song: Option<&CustomSong>,
+    config: &config::Config,
+) -> Result<SongFile, SongError> {
+    match custom_song {
+        Some(custom_song) => open_custom(gd_song, custom_song),
+        None => fetch(gd_song, config, &|_, _| {}, &AtomicBool::new(false)),
+    }
+}
+
+fn fetch(
+    gd_song: &gd::Song,
+    config: &config::Config,
+    progress: &dyn Fn(u64, Option<u64>),
+    cancel: &AtomicBool,
+) -> Result<SongFile, SongError> {
+    match *gd_song {
+        gd::Song::Newgrounds { id } => {
+            let (file, name, artist) = open_newgrounds(gd_song, id, &config.server, progress, cancel)?;
+            Ok(SongFile { file, name, artist, id, custom_offset: None })
+        }
+        gd::Song::Official { id } => {
+            let official = gd::OfficialSong::from_id(id).ok_or(SongError::UnknownOfficialSong(id))?;
+            let file = File::open(config.resources_dir.join(official.file_name))
+                .map_err(|_| SongError::MissingOfficialFile(official.file_name.into()))?;
+            Ok(SongFile {
+                file,
+                name: official.name.into(),
+                artist: official.artist.into(),
+                id,
+                custom_offset: None,
+            })
+        }
+        gd::Song::Unknown => Err(SongError::UnknownSong),
+    }
+}
+
+fn open_custom(gd_song: &gd::Song, custom_song: &CustomSong) -> Result<SongFile, SongError> {
+    let file = File::open(&custom_song.path)
+        .map_err(|_| SongError::MissingCustomFile(custom_song.path.display().to_string()))?;
+    let name = custom_song
+        .path
+        .file_name()
+        .map_or("Custom audio".into(), |name| name.to_string_lossy().into_owned());
+    let id = match *gd_song {
+        gd::Song::Newgrounds { id } | gd::Song::Official { id } => id,
+        gd::Song::Unknown => 0,
+    };
+    Ok(SongFile {
+        file,
+        name,
+        artist: "Local file".into(),
+        id,
+        custom_offset: Some(custom_song.offset),
+    })
+}
+
+/// Opens the song from the GD save folder, downloading it first if needed;
+/// returns the file, name and artist
+fn open_newgrounds(
+    gd_song: &gd::Song,
+    id: i64,
+    server: &gd::Server,
+    progress: &dyn Fn(u64, Option<u64>),
+    cancel: &AtomicBool,
+) -> Result<(File, String, String), SongError> {
+    let song_result = song_cache::lookup(gd_song, server);
+    let song_path = gd::save_path().join(format!("{id}.mp3"));
+
+    match (File::open(&song_path), song_result) {
+        (Ok(file), response) => {
+            let response = response.ok();
+            let name = response
+                .as_ref()
+                .and_then(|response| response.name().map(Into::into))
+                .unwrap_or("Missing Name".into());
+            let artist = response
+                .as_ref()
+                .and_then(|response| response.artist_name().map(Into::into))
+                .unwrap_or_default();
+
+            Ok((file, name, artist))
+        }
+        (Err(_), Ok(response)) => {
+            let link = response.download_link().ok_or(SongError::MissingLink)?;
+            let file = download(&link, &song_path, response.size(), progress, cancel)?;
+
+            Ok((
+                file,
+                response.name().unwrap_or_default().into(),
+                response.artist_name().unwrap_or_default().into(),
+            ))
+        }
+        (Err(_), Err(err)) => Err(err.into()),
+    }
+}
+
+/// Downloads `link` to `dest`, which only appears once the download is complete and matches
+/// `expected_mb`; returns the finished file, opened for decoding
+fn download(
+    link: &str,
+    dest: &Path,
+    expected_mb: Option<f64>,
+    progress: &dyn Fn(u64, Option<u64>),
+    cancel: &AtomicBool,
+) -> Result<File, SongError> {
+    let temp_path = dest.with_extension("mp3.part");
+    let result = File::create(&temp_path)
+        .map_err(SongError::from)
+        .and_then(|temp_file| write_download(link, temp_file, expected_mb, progress, cancel))
+        .and_then(|_| Ok(fs::rename(&temp_path, dest)?));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    Ok(File::open(dest)?)
+}
+
+fn write_download(
+    link: &str,
+    mut file: File,
+    expected_mb: Option<f64>,
+    progress: &dyn Fn(u64, Option<u64>),
+    cancel: &AtomicBool,
+) -> Result<(), SongError> {
+    let mut response = req::get(link)?.error_for_status()?;
+    let total = response.content_length();
+    let mut received = 0;
+    let mut chunk = vec![0; CHUNK_SIZE];
+    progress(0, total);
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(SongError::Cancelled);
+        }
+        let read = response.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&chunk[..read])?;
+        received += read as u64;
+        progress(received, total);
+    }
+    file.sync_all()?;
+
+    if let Some(expected) = total.filter(|&expected| expected != received) {
+        return Err(SongError::IncompleteDownload { received, expected });
+    }
+    let actual = received as f64 / (1024.0 * 1024.0);
+    match expected_mb {
+        Some(expected) if (actual - expected).abs() > SIZE_TOLERANCE_MB => {
+            Err(SongError::SizeMismatch { actual, expected })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{stand_in, ScratchDir};
+
+    /// Serves `body` to a single download, claiming it is `claimed_length` bytes long
+    fn song_link(body: Vec<u8>, claimed_length: usize) -> String {
+        let (root, _request) = stand_in(body, claimed_length);
+        format!("{root}/song.mp3")
+    }
+
+    #[test]
+    fn download_lands_in_place() {
+        let body: Vec<u8> = (0..3 * 1024 * 1024).map(|i| i as u8).collect();
+        let link = song_link(body.clone(), body.len());
+        let dir = ScratchDir::new("complete");
+        let dest = dir.path().join("1.mp3");
+
+        let mut file = download(&link, &dest, Some(3.0), &|_, _| {}, &AtomicBool::new(false)).unwrap();
+        let mut saved = Vec::new();
+        file.read_to_end(&mut saved).unwrap();
+        assert!(saved == body);
+        assert!(!dest.with_extension("mp3.part").exists());
+    }
+
+    #[test]
+    fn wrong_size_is_discarded() {
+        let body = vec![0; 1024 * 1024];
+        let link = song_link(body, 1024 * 1024);
+        let dir = ScratchDir::new("mismatch");
+        let dest = dir.path().join("2.mp3");
+
+        let result = download(&link, &dest, Some(9.79), &|_, _| {}, &AtomicBool::new(false));
+        assert!(matches!(result, Err(SongError::SizeMismatch { .. })), "{result:?}");
+        assert!(!dest.exists());
+        assert!(!dest.with_extension("mp3.part").exists());
+    }
+
+    #[test]
+    fn cut_off_download_is_discarded() {
+        let link = song_link(vec![0; 1000], 5000);
+        let dir = ScratchDir::new("cut-off");
+        let dest = dir.path().join("3.mp3");
+
+        let result = download(&link, &dest, None, &|_, _| {}, &AtomicBool::new(false));
+        assert!(result.is_err());
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn cancelled_download_is_discarded() {
+        let link = song_link(vec![0; 1000], 1000);
+        let dir = ScratchDir::new("cancelled");
+        let dest = dir.path().join("4.mp3");
+
+        let result = download(&link, &dest, None, &|_, _| {}, &AtomicBool::new(true));
+        assert!(matches!(result, Err(SongError::Cancelled)));
+        assert!(!dest.exists());
+    }
+}
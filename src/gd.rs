@@ -4,23 +4,67 @@ use std::time::Duration;
 use eframe::egui::TextFormat;
 use eframe::epaint::text::LayoutJob;
 use flate2::read::GzDecoder;
-use gd_plist::Value;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use gd_plist::{Dictionary, Value};
 use itertools::Itertools;
 use reqwest::blocking as req;
-use std::fs::File;
-use std::io::{Cursor, Read};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::fmt::Write as _;
+use std::io::{Cursor, Read, Write};
 use std::num::ParseIntError;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Song {
     Official { id: i64 /*k8*/ },
     Newgrounds { id: i64 /*k45*/ },
     Unknown,
 }
 
-#[derive(Clone, Debug)]
+/// The game server to ask for song info; a private server (GDPS) or a local mock works too
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Server {
+    pub song_info_url: String,
+    pub secret: String,
+}
+
+/// A song from the main game's soundtrack
+#[derive(Clone, Copy, Debug)]
+pub struct OfficialSong {
+    pub file_name: &'static str,
+    pub name: &'static str,
+    pub artist: &'static str,
+}
+
+/// Indexed by official song id (k8)
+const OFFICIAL_SONGS: [OfficialSong; 21] = [
+    OfficialSong { file_name: "StereoMadness.mp3", name: "Stereo Madness", artist: "ForeverBound" },
+    OfficialSong { file_name: "BackOnTrack.mp3", name: "Back On Track", artist: "DJVI" },
+    OfficialSong { file_name: "Polargeist.mp3", name: "Polargeist", artist: "Step" },
+    OfficialSong { file_name: "DryOut.mp3", name: "Dry Out", artist: "DJVI" },
+    OfficialSong { file_name: "BaseAfterBase.mp3", name: "Base After Base", artist: "DJVI" },
+    OfficialSong { file_name: "CantLetGo.mp3", name: "Can't Let Go", artist: "DJVI" },
+    OfficialSong { file_name: "Jumper.mp3", name: "Jumper", artist: "Waterflame" },
+    OfficialSong { file_name: "TimeMachine.mp3", name: "Time Machine", artist: "Waterflame" },
+    OfficialSong { file_name: "Cycles.mp3", name: "Cycles", artist: "DJVI" },
+    OfficialSong { file_name: "xStep.mp3", name: "xStep", artist: "DJVI" },
+    OfficialSong { file_name: "Clutterfunk.mp3", name: "Clutterfunk", artist: "Waterflame" },
+    OfficialSong { file_name: "TheoryOfEverything.mp3", name: "Theory of Everything", artist: "DJ-Nate" },
+    OfficialSong { file_name: "Electroman.mp3", name: "Electroman Adventures", artist: "Waterflame" },
+    OfficialSong { file_name: "Clubstep.mp3", name: "Clubstep", artist: "DJ-Nate" },
+    OfficialSong { file_name: "Electrodynamix.mp3", name: "Electrodynamix", artist: "DJ-Nate" },
+    OfficialSong { file_name: "HexagonForce.mp3", name: "Hexagon Force", artist: "Waterflame" },
+    OfficialSong { file_name: "BlastProcessing.mp3", name: "Blast Processing", artist: "Waterflame" },
+    OfficialSong { file_name: "TheoryOfEverything2.mp3", name: "Theory of Everything 2", artist: "DJ-Nate" },
+    OfficialSong { file_name: "GeometricalDominator.mp3", name: "Geometrical Dominator", artist: "Waterflame" },
+    OfficialSong { file_name: "Deadlocked.mp3", name: "Deadlocked", artist: "F-777" },
+    OfficialSong { file_name: "Fingerdash.mp3", name: "Fingerdash", artist: "MDK" },
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SongResponse([Option<String>; 10]);
 
 #[derive(Debug, Clone)]
@@ -28,6 +72,15 @@ pub struct Level {
     name: String,          // k2
     revision: Option<i64>, // k46
     song: Song,            // k8 or k45
+    source: LevelSource,
+    save_key: Option<String>, // e.g. k_3 in LLM_01, for levels read from a save
+}
+
+/// Where a level's full properties live
+#[derive(Debug, Clone)]
+enum LevelSource {
+    Save, // CCLocalLevels.dat
+    Properties(Box<Dictionary>), // e.g. read from a .gmd file
 }
 
 #[derive(Debug)]
@@ -40,16 +93,89 @@ pub struct RawLinesTriplet {
     pub green: Lines<Duration>,  // 1.0
 }
 
+/// The creator's own levels, as saved in CCLocalLevels.dat
+pub struct LocalLevels {
+    path: PathBuf,
+    root: Dictionary,
+}
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("Couldn't access save file")]
+    Io(#[from] std::io::Error),
+    #[error("Save file is corrupted")]
+    Corrupted,
+    #[error("Save file isn't a valid plist")]
+    Parse(#[from] gd_plist::Error),
+    #[error("The game can't store the kind of value {0} has")]
+    Unsupported(String),
+}
+
 #[derive(Debug, Error)]
 pub enum SongRequestError {
     #[error("Request failed")]
     ConnectionFailure(#[from] reqwest::Error),
     #[error("Index is not an int?????")]
     ParseFailure(#[from] ParseIntError),
+    #[error("Field index {0} is out of range")]
+    InvalidIndex(usize),
+    #[error("Song doesn't exist on the server")]
+    NotFound,
+    #[error("Song is not allowed for use")]
+    Disallowed,
+    #[error("Server replied with error code {0}")]
+    ServerError(i32),
     #[error("Not a Newgrounds song")]
     NotNewgrounds,
 }
 
+impl RawLinesTriplet {
+    /// Moves every line `offset` seconds later (or earlier); lines pushed before the song start are dropped
+    pub fn shifted(&self, offset: f64) -> Self {
+        let shift = |lines: &Lines<Duration>| {
+            let mut shifted = Lines::new();
+            for time in lines.get_positions() {
+                if let Ok(time) = Duration::try_from_secs_f64(time.as_secs_f64() + offset) {
+                    shifted.insert(time);
+                }
+            }
+            shifted
+        };
+        Self {
+            orange: shift(&self.orange),
+            yellow: shift(&self.yellow),
+            green: shift(&self.green),
+        }
+    }
+
+    /// The lines a guideline colour code stands for: 0.8 orange, 0.9 yellow, 1 green
+    pub fn by_code(&mut self, code: &str) -> Option<&mut Lines<Duration>> {
+        match code.parse::<f64>().map(|x| (10f64 * x).round() as i32) {
+            Ok(8) => Some(&mut self.orange),
+            Ok(9) => Some(&mut self.yellow),
+            Ok(10) => Some(&mut self.green),
+            _ => None,
+        }
+    }
+
+    /// Each colour's lines with the code the game stores them under; `by_code` reads them back
+    pub fn with_codes(&self) -> [(&Lines<Duration>, &'static str); 3] {
+        [(&self.orange, "0.8"), (&self.yellow, "0.9"), (&self.green, "1")]
+    }
+
+    pub fn merge(&mut self, other: RawLinesTriplet) {
+        for (lines, other) in [
+            (&mut self.orange, other.orange),
+            (&mut self.yellow, other.yellow),
+            (&mut self.green, other.green),
+        ] {
+            for &time in other.get_positions() {
+                lines.insert(time);
+            }
+        }
+    }
+}
+
 impl InnerLevel {
     pub fn try_from_encoded_ils(encoded_ils: &str) -> Option<Self> {
         let b64 = URL_SAFE.decode(encoded_ils).ok()?;
@@ -69,61 +195,130 @@ impl InnerLevel {
             .map(|(_, v)| v)
     }
 
+    /// The guidelines, stored as `time~colour~...` in the header's kA14
     pub fn get_lines(&self) -> RawLinesTriplet {
         let mut lines = RawLinesTriplet::default();
-        self.0
+        self.get_property("kA14")
+            .unwrap_or_default()
             .split('~')
             .tuples()
             .for_each(|(timestamp, color_code)| {
-                let Ok(code_num) = color_code.parse::<f64>().map(|x| (10f64 * x).round() as i32) else {
-                    log::info!("{} could not be parsed", color_code);
-                    return;
-                };
                 let Ok(duration) = timestamp.parse::<f64>().map(|t| Duration::from_secs_f64(t)) else {
                     log::info!("{} could not be parsed", timestamp);
                     return;
                 };
-                match code_num {
-                    8 => {lines.orange.insert(duration);},
-                    9 => {lines.yellow.insert(duration);},
-                    10 => {lines.green.insert(duration);},
-                    _ => {log::info!("{} at timestamp {} was invalid", code_num, timestamp)}
+                match lines.by_code(color_code) {
+                    Some(lines) => {lines.insert(duration);},
+                    None => {log::info!("{} at timestamp {} was invalid", color_code, timestamp)}
                 };
             });
         lines
     }
 
+    /// A copy of the level with its guidelines replaced by `lines`
+    pub fn with_lines(&self, lines: &RawLinesTriplet) -> Self {
+        let mut guidelines: Vec<(Duration, &str)> = lines
+            .with_codes()
+            .into_iter()
+            .flat_map(|(lines, code)| lines.get_positions().iter().map(move |&time| (time, code)))
+            .collect();
+        guidelines.sort();
+        let guidelines: String = guidelines
+            .into_iter()
+            .map(|(time, code)| format!("{}~{code}~", time.as_secs_f64()))
+            .collect();
+
+        let (header, objects) = self.0.split_once(';').unwrap_or((&self.0, ""));
+        let mut found = false;
+        let mut header: Vec<(&str, &str)> = header
+            .split(',')
+            .tuples()
+            .map(|(key, value)| match key {
+                "kA14" => {
+                    found = true;
+                    (key, guidelines.as_str())
+                }
+                _ => (key, value),
+            })
+            .collect();
+        if !found {
+            header.push(("kA14", &guidelines));
+        }
+        let header = header.into_iter().map(|(key, value)| format!("{key},{value}")).join(",");
+        Self(format!("{header};{objects}"))
+    }
+
+    /// Gzipped and base64 encoded, the way k4 stores it
+    pub fn encode(&self) -> String {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        // writing to a Vec can't fail
+        encoder.write_all(self.0.as_bytes()).unwrap();
+        URL_SAFE.encode(encoder.finish().unwrap())
+    }
+
     pub fn hash(&self) -> md5::Digest {
         md5::compute(self.0.clone())
     }
 }
 
 impl Song {
-    pub fn get_response(&self) -> Result<SongResponse, SongRequestError> {
+    pub fn get_response(&self, server: &Server) -> Result<SongResponse, SongRequestError> {
         match self {
             Self::Newgrounds { id } => {
-                let mut out = SongResponse(Default::default());
-                req::ClientBuilder::new()
+                let text = req::ClientBuilder::new()
                     .user_agent("")
                     .build()?
-                    .post("http://www.boomlings.com/database/getGJSongInfo.php")
-                    .form(&[("songID", id.to_string().as_ref()), ("secret", "Wmfd2893gb7")])
+                    .post(&server.song_info_url)
+                    .form(&[("songID", id.to_string().as_str()), ("secret", server.secret.as_str())])
                     .send()?
-                    .text()?
-                    .split("~|~")
-                    .array_chunks()
-                    .try_for_each(|[id, value]| -> Result<(), SongRequestError> {
-                        out.0[id.parse::<usize>()? - 1] = Some(value.into());
-                        Ok(())
-                    })
-                    .map(|_| out)
+                    .text()?;
+                SongResponse::parse(&text)
             }
             _ => Err(SongRequestError::NotNewgrounds),
         }
     }
 }
 
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            song_info_url: "http://www.boomlings.com/database/getGJSongInfo.php".into(),
+            secret: "Wmfd2893gb7".into(),
+        }
+    }
+}
+
+impl OfficialSong {
+    pub fn from_id(id: i64) -> Option<&'static Self> {
+        usize::try_from(id).ok().and_then(|idx| OFFICIAL_SONGS.get(idx))
+    }
+}
+
 impl SongResponse {
+    /// Parses a `~|~` separated reply; negative numbers are the server's error codes
+    fn parse(text: &str) -> Result<Self, SongRequestError> {
+        match text.trim().parse::<i32>() {
+            Ok(-1) => return Err(SongRequestError::NotFound),
+            Ok(-2) => return Err(SongRequestError::Disallowed),
+            Ok(code) if code < 0 => return Err(SongRequestError::ServerError(code)),
+            _ => {}
+        }
+        let mut out = SongResponse(Default::default());
+        text.trim()
+            .split("~|~")
+            .array_chunks()
+            .try_for_each(|[idx, value]| -> Result<(), SongRequestError> {
+                let idx = idx.parse::<usize>()?;
+                let field = idx
+                    .checked_sub(1)
+                    .and_then(|i| out.0.get_mut(i))
+                    .ok_or(SongRequestError::InvalidIndex(idx))?;
+                *field = Some(value.into());
+                Ok(())
+            })
+            .map(|_| out)
+    }
+
     pub fn id(&self) -> Option<i32> {
         self.0[0].as_deref().and_then(|s| s.parse().ok())
     }
@@ -136,7 +331,8 @@ impl SongResponse {
     pub fn artist_name(&self) -> Option<&str> {
         self.0[3].as_deref()
     }
-    pub fn size(&self) -> Option<i32> {
+    /// In megabytes (MiB), rounded to two decimals
+    pub fn size(&self) -> Option<f64> {
         self.0[4].as_deref().and_then(|s| s.parse().ok())
     }
     pub fn video_id(&self) -> Option<&str> {
@@ -167,50 +363,60 @@ impl Level {
             .unwrap()
             .into_iter()
             .filter(|(key, _)| key.as_str() != "_isArr")
-            .map(|(_, val)| {
-                let mut builder = LevelBuilder::new();
-                let props = val.as_dictionary().unwrap();
-                if let Some(title) = props.get("k2") {
-                    builder.with_name(title.as_string().unwrap().into());
-                }
-                if let Some(rev) = props.get("k46") {
-                    builder.with_revision(rev.as_signed_integer().unwrap());
-                }
-                if let Some(official_song) = props.get("k8") {
-                    builder.with_song(Song::Official { id: official_song.as_signed_integer().unwrap() });
-                }
-                if let Some(ng_song) = props.get("k45") {
-                    builder.with_song(Song::Newgrounds { id: ng_song.as_signed_integer().unwrap() });
-                }
-                builder.build_level().unwrap()
+            .map(|(key, val)| {
+                let mut level = LevelBuilder::from_properties(val.as_dictionary().unwrap()).build_level().unwrap();
+                level.save_key = Some(key.clone());
+                level
             })
             .collect()
     }
 
+    /// A level from its own copy of its properties (k2, k4, ...); None without a name
+    pub fn from_properties(props: Dictionary) -> Option<Self> {
+        let mut builder = LevelBuilder::from_properties(&props);
+        builder.with_source(LevelSource::Properties(Box::new(props)));
+        builder.build_level()
+    }
+
+    /// All of the level's properties, as the game stores them
+    pub fn properties(&self) -> Dictionary {
+        match &self.source {
+            LevelSource::Properties(props) => (**props).clone(),
+            LevelSource::Save => get_local_level_plist()
+                .as_dictionary()
+                .and_then(|dict| dict.get("LLM_01"))
+                .unwrap()
+                .as_dictionary()
+                .unwrap()
+                .iter()
+                .find(|(key, val)| match &self.save_key {
+                    Some(save_key) => *key == save_key,
+                    None => {
+                        key.as_str() != "_isArr" && {
+                            let props = val.as_dictionary().unwrap();
+                            props.get("k2").unwrap().as_string().unwrap() == self.name
+                                && props.get("k46").and_then(|rev| rev.as_signed_integer()) == self.revision
+                        }
+                    }
+                })
+                .unwrap()
+                .1
+                .as_dictionary()
+                .unwrap()
+                .clone(),
+        }
+    }
+
     pub fn load_inner(&self) -> InnerLevel {
-        get_local_level_plist()
-            .as_dictionary()
-            .and_then(|dict| dict.get("LLM_01"))
-            .unwrap()
-            .as_dictionary()
-            .unwrap()
-            .iter()
-            .find(|(key, val)| {
-                key.as_str() != "_isArr" && {
-                    let props = val.as_dictionary().unwrap();
-                    props.get("k2").unwrap().as_string().unwrap() == self.name
-                        && props.get("k46").and_then(|rev| rev.as_signed_integer()) == self.revision
-                }
-            })
-            .unwrap()
-            .1
-            .as_dictionary()
-            .unwrap()
+        self.inner().unwrap()
+    }
+
+    /// The level data, if it has any that can be decoded
+    pub fn inner(&self) -> Option<InnerLevel> {
+        self.properties()
             .get("k4")
-            .unwrap()
-            .as_string()
+            .and_then(Value::as_string)
             .and_then(InnerLevel::try_from_encoded_ils)
-            .unwrap()
     }
 
     pub fn display_name(&self) -> LayoutJob {
@@ -236,6 +442,14 @@ impl Level {
     pub fn song(&self) -> Song {
         self.song
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn revision(&self) -> Option<i64> {
+        self.revision
+    }
 }
 
 pub fn save_path() -> PathBuf {
@@ -264,11 +478,27 @@ pub fn save_path() -> PathBuf {
     path_buf
 }
 
+/// The game's `Resources` folder, for a default Steam install
+pub fn resources_path() -> PathBuf {
+    #[cfg(unix)]
+    let path_buf = {
+        let mut path_buf = home::home_dir().unwrap();
+        path_buf.extend(
+            [".local", "share", "Steam", "steamapps", "common", "Geometry Dash", "Resources"].iter(),
+        );
+        path_buf
+    };
+    #[cfg(windows)]
+    let path_buf = PathBuf::from(r"C:\Program Files (x86)\Steam\steamapps\common\Geometry Dash\Resources");
+    path_buf
+}
+
 #[derive(Default)]
 struct LevelBuilder {
     name: Option<String>,
     song: Option<Song>,
     revision: Option<i64>,
+    source: Option<LevelSource>,
 }
 
 impl LevelBuilder {
@@ -276,6 +506,23 @@ impl LevelBuilder {
         Self::default()
     }
 
+    fn from_properties(props: &Dictionary) -> Self {
+        let mut builder = Self::new();
+        if let Some(title) = props.get("k2").and_then(Value::as_string) {
+            builder.with_name(title.into());
+        }
+        if let Some(rev) = props.get("k46").and_then(Value::as_signed_integer) {
+            builder.with_revision(rev);
+        }
+        if let Some(official_song) = props.get("k8").and_then(Value::as_signed_integer) {
+            builder.with_song(Song::Official { id: official_song });
+        }
+        if let Some(ng_song) = props.get("k45").and_then(Value::as_signed_integer) {
+            builder.with_song(Song::Newgrounds { id: ng_song });
+        }
+        builder
+    }
+
     fn with_name(&mut self, name: String) {
         self.name = Some(name);
     }
@@ -288,13 +535,24 @@ impl LevelBuilder {
         self.revision = Some(revision);
     }
 
+    fn with_source(&mut self, source: LevelSource) {
+        self.source = Some(source);
+    }
+
     fn build_level(self) -> Option<Level> {
         match self {
             Self {
                 name: Some(name),
                 revision,
                 song,
-            } => Some(Level { name, revision, song: song.unwrap_or(Song::Unknown)}),
+                source,
+            } => Some(Level {
+                name,
+                revision,
+                song: song.unwrap_or(Song::Official { id: 0 }), // k8 is left out for Stereo Madness
+                source: source.unwrap_or(LevelSource::Save),
+                save_key: None,
+            }),
             _ => None,
         }
     }
@@ -308,16 +566,349 @@ fn get_local_level_plist() -> Value {
         save_file.read_to_end(&mut sd).unwrap();
         sd
     };
+    let plist = decrypt_save(&raw_save_data).unwrap();
+    Value::from_reader(Cursor::new(plist)).unwrap()
+}
+
+/// Undoes the save file's xor, base64 and gzip layers
+fn decrypt_save(raw_save_data: &[u8]) -> Option<String> {
     let data_post_xor: Vec<u8> = raw_save_data
         .iter()
         .map(|b| b ^ 11)
         .filter(|&b| b != 0u8)
         .collect();
-    let data_post_b64 = URL_SAFE.decode(data_post_xor).unwrap();
+    let data_post_b64 = URL_SAFE.decode(data_post_xor).ok()?;
     let mut decoder = GzDecoder::<&[u8]>::new(data_post_b64.as_ref());
     let mut plist = String::new();
     if decoder.read_to_string(&mut plist).is_err() {
         log::warn!("Game save likely corrupted (gzip decode failed)");
     }
-    Value::from_reader(Cursor::new(plist)).unwrap()
+    Some(plist)
+}
+
+fn encrypt_save(plist: &str) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    // writing to a Vec can't fail
+    encoder.write_all(plist.as_bytes()).unwrap();
+    URL_SAFE
+        .encode(encoder.finish().unwrap())
+        .into_bytes()
+        .into_iter()
+        .map(|b| b ^ 11)
+        .collect()
+}
+
+impl LocalLevels {
+    pub fn default_path() -> PathBuf {
+        save_path().join("CCLocalLevels.dat")
+    }
+
+    pub fn open(path: &Path) -> Result<Self, SaveError> {
+        let plist = decrypt_save(&fs::read(path)?).ok_or(SaveError::Corrupted)?;
+        let root = Value::from_reader(Cursor::new(plist))?
+            .into_dictionary()
+            .ok_or(SaveError::Corrupted)?;
+        Ok(Self { path: path.into(), root })
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (&String, &Dictionary)> {
+        self.root
+            .get("LLM_01")
+            .and_then(Value::as_dictionary)
+            .into_iter()
+            .flat_map(|levels| levels.iter())
+            .filter(|(key, _)| key.as_str() != "_isArr")
+            .filter_map(|(key, val)| Some((key, val.as_dictionary()?)))
+    }
+
+    /// Every level in the save, in the game's order (newest first), carrying its properties
+    /// and the key of its entry
+    pub fn levels(&self) -> Vec<Level> {
+        self.entries()
+            .filter_map(|(key, props)| {
+                let mut level = Level::from_properties(props.clone())?;
+                level.save_key = Some(key.clone());
+                Some(level)
+            })
+            .collect()
+    }
+
+    /// Replaces the level data (k4) of `level`, which has to come from `levels()`; false if the
+    /// save has no such level
+    pub fn set_inner(&mut self, level: &Level, inner: &InnerLevel) -> bool {
+        let Some(key) = &level.save_key else {
+            return false;
+        };
+        let entry = self
+            .root
+            .get_mut("LLM_01")
+            .and_then(Value::as_dictionary_mut)
+            .and_then(|levels| levels.get_mut(key))
+            .and_then(Value::as_dictionary_mut);
+        match entry {
+            Some(props) => {
+                props.insert("k4".into(), Value::String(inner.encode()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Writes the save back where it came from, keeping the previous one as a `.bak`
+    pub fn write(&self) -> Result<(), SaveError> {
+        let xml = to_xml(&self.root)?;
+        if self.path.exists() {
+            fs::copy(&self.path, self.path.with_extension("dat.bak"))?;
+        }
+        fs::write(&self.path, encrypt_save(&xml))?;
+        Ok(())
+    }
+}
+
+/// The plist dialect the game writes, with its one-letter tags
+pub fn to_xml(props: &Dictionary) -> Result<String, SaveError> {
+    let mut xml = String::from(r#"<?xml version="1.0"?><plist version="1.0" gjver="2.0">"#);
+    xml.push_str("<dict>");
+    write_entries(&mut xml, props)?;
+    xml.push_str("</dict></plist>");
+    Ok(xml)
+}
+
+/// Writes the keys and values of `dict`; nested dictionaries get the game's short `<d>` tag
+fn write_entries(xml: &mut String, dict: &Dictionary) -> Result<(), SaveError> {
+    for (key, value) in dict.iter() {
+        let start = xml.len();
+        let _ = write!(xml, "<k>{}</k>", escape(key));
+        match value {
+            Value::Dictionary(dict) => {
+                xml.push_str("<d>");
+                write_entries(xml, dict)?;
+                xml.push_str("</d>");
+            }
+            Value::String(s) => {
+                let _ = write!(xml, "<s>{}</s>", escape(s));
+            }
+            Value::Integer(i) => {
+                let _ = write!(xml, "<i>{i}</i>");
+            }
+            Value::Real(r) => {
+                let _ = write!(xml, "<r>{r}</r>");
+            }
+            Value::Boolean(true) => xml.push_str("<t/>"),
+            // the game leaves out false booleans instead of writing them, and reads a missing
+            // key as false
+            Value::Boolean(false) => xml.truncate(start),
+            other => {
+                log::error!("Can't write {key}: the game doesn't store {other:?}");
+                return Err(SaveError::Unsupported(key.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::sync::mpsc;
+
+    /// Serves `body` to a single request, handing the request's form body back
+    fn stand_in(body: &'static str) -> (Server, mpsc::Receiver<String>) {
+        let (root, form) = test_util::stand_in(body, body.len());
+        let server = Server {
+            song_info_url: format!("{root}/database/getGJSongInfo.php"),
+            secret: "test-secret".into(),
+        };
+        (server, form)
+    }
+
+    #[test]
+    fn parses_song_info() {
+        let (server, form) = stand_in(
+            "1~|~803223~|~2~|~Xtrullor - Supernova~|~3~|~51~|~4~|~Xtrullor~|~5~|~9.79\
+             ~|~6~|~~|~10~|~http%3A%2F%2Faudio.ngfiles.com%2F803000%2F803223_Supernova.mp3~|~7~|~~|~8~|~1",
+        );
+        let response = Song::Newgrounds { id: 803223 }.get_response(&server).unwrap();
+        assert_eq!(response.id(), Some(803223));
+        assert_eq!(response.name(), Some("Xtrullor - Supernova"));
+        assert_eq!(response.artist_name(), Some("Xtrullor"));
+        assert_eq!(response.size(), Some(9.79));
+        assert_eq!(
+            response.download_link().as_deref(),
+            Some("http://audio.ngfiles.com/803000/803223_Supernova.mp3")
+        );
+        let form = form.recv().unwrap();
+        assert!(form.contains("songID=803223"), "{form}");
+        assert!(form.contains("secret=test-secret"), "{form}");
+    }
+
+    #[test]
+    fn missing_song_is_not_found() {
+        let (server, _form) = stand_in("-1");
+        assert!(matches!(
+            Song::Newgrounds { id: 1 }.get_response(&server),
+            Err(SongRequestError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn banned_song_is_disallowed() {
+        let (server, _form) = stand_in("-2");
+        let err = Song::Newgrounds { id: 1 }.get_response(&server).unwrap_err();
+        assert!(matches!(err, SongRequestError::Disallowed));
+        assert_eq!(err.to_string(), "Song is not allowed for use");
+    }
+
+    #[test]
+    fn out_of_range_index_is_rejected() {
+        assert!(matches!(
+            SongResponse::parse("0~|~nothing"),
+            Err(SongRequestError::InvalidIndex(0))
+        ));
+        assert!(matches!(
+            SongResponse::parse("1~|~5~|~11~|~too far"),
+            Err(SongRequestError::InvalidIndex(11))
+        ));
+    }
+
+    fn secs(lines: &Lines<Duration>) -> Vec<f64> {
+        lines.get_positions().iter().map(Duration::as_secs_f64).collect()
+    }
+
+    #[test]
+    fn shifting_drops_lines_before_the_start() {
+        let mut lines = RawLinesTriplet::default();
+        lines.green.insert(Duration::from_millis(500));
+        lines.green.insert(Duration::from_secs(2));
+        lines.orange.insert(Duration::from_millis(1250));
+
+        let later = lines.shifted(1.5);
+        assert_eq!(secs(&later.green), [2.0, 3.5]);
+        assert_eq!(secs(&later.orange), [2.75]);
+        assert!(later.yellow.empty());
+
+        let earlier = lines.shifted(-1.0);
+        assert_eq!(secs(&earlier.green), [1.0]);
+        assert_eq!(secs(&earlier.orange), [0.25]);
+        // lines stay put without an offset
+        assert_eq!(secs(&lines.shifted(0.0).green), secs(&lines.green));
+    }
+
+    #[test]
+    fn merging_keeps_both_sets_of_lines() {
+        let mut lines = RawLinesTriplet::default();
+        lines.green.insert(Duration::from_secs(1));
+        lines.yellow.insert(Duration::from_secs(2));
+        let mut other = RawLinesTriplet::default();
+        other.green.insert(Duration::from_secs(1));
+        other.green.insert(Duration::from_secs(3));
+        other.orange.insert(Duration::from_secs(4));
+
+        lines.merge(other);
+        assert_eq!(secs(&lines.green), [1.0, 3.0]);
+        assert_eq!(secs(&lines.yellow), [2.0]);
+        assert_eq!(secs(&lines.orange), [4.0]);
+    }
+
+    /// Header and first objects of a level saved by the game (2.1), with three guidelines
+    const REAL_LEVEL: &str = "kS38,1_40_2_125_3_255_11_255_12_255_13_255_4_-1_6_1000_7_1_15_1_18_0_8_1|\
+        1_0_2_102_3_255_11_255_12_255_13_255_4_-1_6_1001_7_1_15_1_18_0_8_1|,kA13,0,kA15,0,kA16,0,\
+        kA14,1.2~0.8~2.4~1~3.6~0.9~,kA6,0,kA7,0,kA17,0,kA18,0,kS39,0,kA2,0,kA3,0,kA8,0,kA4,0,kA9,0,\
+        kA10,0,kA11,0;1,1,2,75,3,15;1,8,2,165,3,15;";
+
+    #[test]
+    fn reads_guidelines_from_a_real_level() {
+        // splitting the whole level on ~ used to glue the first time onto the header, losing
+        // that line
+        let lines = InnerLevel(REAL_LEVEL.into()).get_lines();
+        assert_eq!(secs(&lines.orange), [1.2]);
+        assert_eq!(secs(&lines.green), [2.4]);
+        assert_eq!(secs(&lines.yellow), [3.6]);
+
+        let untouched = InnerLevel(REAL_LEVEL.into()).with_lines(&lines);
+        assert_eq!(untouched.0, REAL_LEVEL);
+    }
+
+    #[test]
+    fn guidelines_round_trip() {
+        let level = InnerLevel("kS38,1_40,kA13,0,kA14,,kA6,0;1,1,2,15,3,15;".into());
+        let mut lines = RawLinesTriplet::default();
+        lines.orange.insert(Duration::from_millis(500));
+        lines.green.insert(Duration::from_millis(1250));
+        lines.yellow.insert(Duration::from_secs(2));
+
+        let edited = level.with_lines(&lines);
+        assert_eq!(edited.0, "kS38,1_40,kA13,0,kA14,0.5~0.8~1.25~1~2~0.9~,kA6,0;1,1,2,15,3,15;");
+        let decoded = InnerLevel::try_from_encoded_ils(&edited.encode()).unwrap();
+        assert_eq!(decoded.0, edited.0);
+        let read = decoded.get_lines();
+        assert_eq!(read.orange.get_positions(), lines.orange.get_positions());
+        assert_eq!(read.yellow.get_positions(), lines.yellow.get_positions());
+        assert_eq!(read.green.get_positions(), lines.green.get_positions());
+    }
+
+    /// A save with two levels that share a name and revision
+    fn twin_save() -> String {
+        let inner = InnerLevel("kS38,1_40,kA13,0,kA14,,kA6,0;1,1,2,15,3,15;".into()).encode();
+        format!(
+            concat!(
+                r#"<?xml version="1.0"?><plist version="1.0" gjver="2.0"><dict><k>LLM_01</k><d><k>_isArr</k><t/>"#,
+                r#"<k>k_0</k><d><k>kCEK</k><i>4</i><k>k2</k><s>Twins &amp; Co</s><k>k4</k><s>{inner}</s>"#,
+                r#"<k>k45</k><i>803223</i><k>k13</k><t/><k>k80</k><r>12.5</r></d>"#,
+                r#"<k>k_1</k><d><k>kCEK</k><i>4</i><k>k2</k><s>Twins &amp; Co</s><k>k4</k><s>{inner}</s>"#,
+                r#"<k>k8</k><i>3</i><k>k80</k><r>40</r></d></d><k>LLM_02</k><i>35</i></dict></plist>"#
+            ),
+            inner = inner
+        )
+    }
+
+    #[test]
+    fn save_round_trip() {
+        let dir = test_util::ScratchDir::new("save");
+        let path = dir.path().join("CCLocalLevels.dat");
+        fs::write(&path, encrypt_save(&twin_save())).unwrap();
+
+        let mut save = LocalLevels::open(&path).unwrap();
+        let before = save.root.clone();
+        let levels = save.levels();
+        assert_eq!(levels.len(), 2);
+        let second = levels.iter().find(|level| level.save_key.as_deref() == Some("k_1")).unwrap();
+        assert_eq!(second.song, Song::Official { id: 3 });
+
+        let mut lines = RawLinesTriplet::default();
+        lines.green.insert(Duration::from_millis(1250));
+        assert!(save.set_inner(second, &second.load_inner().with_lines(&lines)));
+        save.write().unwrap();
+        assert!(path.with_extension("dat.bak").exists());
+
+        let reopened = LocalLevels::open(&path).unwrap();
+        let entry = |root: &Dictionary, key: &str| {
+            root.get("LLM_01").unwrap().as_dictionary().unwrap().get(key).unwrap().as_dictionary().unwrap().clone()
+        };
+        assert_eq!(entry(&reopened.root, "k_0"), entry(&before, "k_0"));
+        assert_eq!(reopened.root.get("LLM_02"), before.get("LLM_02"));
+        let mut edited = entry(&reopened.root, "k_1");
+        let mut original = entry(&before, "k_1");
+        edited.remove("k4");
+        original.remove("k4");
+        assert_eq!(edited, original);
+
+        let levels = reopened.levels();
+        assert!(levels[0].load_inner().get_lines().green.get_positions().is_empty());
+        let green = levels[1].load_inner().get_lines().green;
+        assert_eq!(green.get_positions(), lines.green.get_positions());
+    }
+
+    #[test]
+    fn official_song_is_not_requested() {
+        assert!(matches!(
+            Song::Official { id: 3 }.get_response(&Server::default()),
+            Err(SongRequestError::NotNewgrounds)
+        ));
+    }
 }
@@ -0,0 +1,94 @@
+use crate::gd::{self, InnerLevel};
+use gd_plist::Value;
+use std::fs::{self, File};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GmdError {
+    #[error("Couldn't access level file")]
+    Io(#[from] std::io::Error),
+    #[error("Not a valid level file")]
+    Parse(#[from] gd_plist::Error),
+    #[error("Level file has no name or level data")]
+    NotALevel,
+    #[error(transparent)]
+    Save(#[from] gd::SaveError),
+}
+
+/// Reads a single shared level (GDShare's `.gmd`, the level's properties as a plist)
+pub fn import(path: &Path) -> Result<gd::Level, GmdError> {
+    let props = Value::from_reader(File::open(path)?)?
+        .into_dictionary()
+        .filter(|props| props.contains_key("k4"))
+        .ok_or(GmdError::NotALevel)?;
+    gd::Level::from_properties(props).ok_or(GmdError::NotALevel)
+}
+
+/// Writes `level` with `inner` as its level data (k4)
+pub fn export(path: &Path, level: &gd::Level, inner: &InnerLevel) -> Result<(), GmdError> {
+    let mut props = level.properties();
+    props.insert("k4".into(), Value::String(inner.encode()));
+    fs::write(path, gd::to_xml(&props)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScratchDir;
+    use base64::engine::{general_purpose::URL_SAFE, Engine};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use gd_plist::Dictionary;
+    use std::io::Write;
+    use std::time::Duration;
+
+    fn encoded(ils: &str) -> String {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(ils.as_bytes()).unwrap();
+        URL_SAFE.encode(encoder.finish().unwrap())
+    }
+
+    fn shared_level() -> gd::Level {
+        let mut props = Dictionary::new();
+        props.insert("k2".into(), Value::String("Shared <3".into()));
+        props.insert("k4".into(), Value::String(encoded("kS38,1_40,kA13,0,kA14,,kA6,0;1,1,2,15,3,15;")));
+        props.insert("k5".into(), Value::String("someone".into()));
+        props.insert("k45".into(), Value::Integer(803223i64.into()));
+        props.insert("k46".into(), Value::Integer(3i64.into()));
+        gd::Level::from_properties(props).unwrap()
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let dir = ScratchDir::new("gmd");
+        let path = dir.path().join("Shared.gmd");
+        let level = shared_level();
+        let mut lines = gd::RawLinesTriplet::default();
+        lines.green.insert(Duration::from_millis(1500));
+        lines.orange.insert(Duration::from_millis(250));
+        lines.yellow.insert(Duration::from_secs(3));
+
+        export(&path, &level, &level.load_inner().with_lines(&lines)).unwrap();
+        let imported = import(&path).unwrap();
+        assert_eq!(imported.name(), "Shared <3");
+        assert_eq!(imported.revision(), Some(3));
+        assert_eq!(imported.song(), gd::Song::Newgrounds { id: 803223 });
+        assert_eq!(imported.properties().get("k5"), level.properties().get("k5"));
+        let read = imported.load_inner().get_lines();
+        assert_eq!(read.green.get_positions(), lines.green.get_positions());
+        assert_eq!(read.orange.get_positions(), lines.orange.get_positions());
+        assert_eq!(read.yellow.get_positions(), lines.yellow.get_positions());
+    }
+
+    #[test]
+    fn level_data_is_required() {
+        let dir = ScratchDir::new("gmd-empty");
+        let path = dir.path().join("Empty.gmd");
+        let mut props = shared_level().properties();
+        props.remove("k4");
+        fs::write(&path, gd::to_xml(&props).unwrap()).unwrap();
+        assert!(matches!(import(&path), Err(GmdError::NotALevel)));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::gd::RawLinesTriplet;
+use crate::music::Lines;
+use std::fmt::Write;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LabelError {
+    #[error("Line {0} of the label file is malformed")]
+    Malformed(usize),
+}
+
+/// Which lines a label's text selects: a colour's initial or name, or its GD colour code
+pub fn lines_for<'a>(lines: &'a mut RawLinesTriplet, text: &str) -> Option<&'a mut Lines<Duration>> {
+    match text.to_ascii_lowercase().as_str() {
+        "o" | "orange" => Some(&mut lines.orange),
+        "y" | "yellow" => Some(&mut lines.yellow),
+        "g" | "green" => Some(&mut lines.green),
+        code => lines.by_code(code),
+    }
+}
+
+/// Reads an Audacity label track export (`start\tend\tlabel` per line). Each label becomes a
+/// line at its start; labels that don't name a colour are skipped.
+pub fn import(text: &str) -> Result<RawLinesTriplet, LabelError> {
+    let mut lines = RawLinesTriplet::default();
+    for (idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Audacity writes the frequency range of spectral labels on an extra line starting with "\"
+        if line.starts_with('\\') {
+            continue;
+        }
+        let mut fields = line.split('\t');
+        let start = fields
+            .next()
+            .and_then(|f| f.trim().parse::<f64>().ok())
+            .filter(|start| start.is_finite() && *start >= 0.0)
+            .ok_or(LabelError::Malformed(idx + 1))?;
+        let label = fields.nth(1).unwrap_or("").trim();
+        match lines_for(&mut lines, label) {
+            Some(lines) => {
+                lines.insert(Duration::from_secs_f64(start));
+            }
+            None => log::info!("Label {label:?} at {start}s doesn't name a colour"),
+        }
+    }
+    Ok(lines)
+}
+
+/// Writes every line as a point label named after its colour's initial
+pub fn export(lines: &RawLinesTriplet) -> String {
+    let mut labels: Vec<(Duration, &str)> = [(&lines.orange, "o"), (&lines.yellow, "y"), (&lines.green, "g")]
+        .into_iter()
+        .flat_map(|(lines, label)| lines.get_positions().iter().map(move |&time| (time, label)))
+        .collect();
+    labels.sort();
+    let mut text = String::new();
+    for (time, label) in labels {
+        let secs = time.as_secs_f64();
+        let _ = writeln!(text, "{secs:.6}\t{secs:.6}\t{label}");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(lines: &Lines<Duration>) -> Vec<f64> {
+        lines.get_positions().iter().map(Duration::as_secs_f64).collect()
+    }
+
+    #[test]
+    fn imports_colours() {
+        let text = "0.500000\t0.500000\tg\n1.250000\t1.500000\tY\n2.000000\t2.000000\t0.8\n\
+                    \\\t100.0\t2000.0\n3.000000\t3.000000\tchorus\n4.000000\t4.000000\t1\n";
+        let lines = import(text).unwrap();
+        assert_eq!(secs(&lines.green), [0.5, 4.0]);
+        assert_eq!(secs(&lines.yellow), [1.25]);
+        assert_eq!(secs(&lines.orange), [2.0]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut lines = RawLinesTriplet::default();
+        lines.green.insert(Duration::from_millis(1500));
+        lines.orange.insert(Duration::from_millis(250));
+        lines.yellow.insert(Duration::from_millis(3000));
+        let text = export(&lines);
+        assert_eq!(text, "0.250000\t0.250000\to\n1.500000\t1.500000\tg\n3.000000\t3.000000\ty\n");
+        let back = import(&text).unwrap();
+        assert_eq!(secs(&back.green), [1.5]);
+        assert_eq!(secs(&back.orange), [0.25]);
+        assert_eq!(secs(&back.yellow), [3.0]);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(matches!(import("0.5\t0.5\tg\nabc\tdef\tg\n"), Err(LabelError::Malformed(2))));
+        assert!(matches!(import("-1\t-1\tg\n"), Err(LabelError::Malformed(1))));
+    }
+}
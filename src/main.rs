@@ -2,20 +2,37 @@
 //#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
 #![allow(dead_code)]
 
+mod analysis;
+mod audio;
+mod cli;
+mod config;
+mod fetch;
 mod gd;
+mod gmd;
+mod labels;
+mod midi;
 mod music;
+mod osu;
+mod playback;
+mod project;
+mod song_cache;
+mod tap;
+#[cfg(test)]
+mod test_util;
 
 use eframe::egui;
-use reqwest::blocking as req;
 use std::boxed::Box;
 use std::collections::VecDeque;
 use std::error::Error;
-use std::fs::File;
-use std::io::Write;
+use std::fs::{self, File};
 use std::mem;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use std::time;
-use rodio::source::Source;
+
+const AUTOSAVE_INTERVAL: time::Duration = time::Duration::from_secs(30);
+/// How often to check on a song that is being fetched
+const FETCH_POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
 
 fn project_dirs() -> directories::ProjectDirs {
     directories::ProjectDirs::from("xyz", "interestingzinc", "pipedash").expect("Home dir missing?")
@@ -28,9 +45,50 @@ struct PipeDash {
     loaded_level_checksum: Option<(gd::Level, md5::Digest)>,
     editor_mode: EditorMode,
     errors: VecDeque<Box<dyn Error>>,
+    recovered_session: Option<project::ProjectFile>,
+    last_autosave: time::Instant,
+    config: config::Config,
+    settings_open: bool,
+    calibration_open: bool,
+    pending_load: Option<PendingLoad>,
+    transfer: TransferWindow,
+    copy_lines: CopyLinesWindow,
+    analysis: AnalysisWindow,
+}
+
+/// The import/export window
+struct TransferWindow {
+    open: bool,
+    path: String,
+    midi_mappings: Vec<midi::NoteMapping>,
+    osu_hit_objects: Option<Color>,
+}
+
+/// Copying the guidelines of the selected level into the open one
+#[derive(Default)]
+struct CopyLinesWindow {
+    open: bool,
+    offset: f64, // seconds
+    replace: bool,
+}
+
+/// The guideline report of the open level
+#[derive(Default)]
+struct AnalysisWindow {
+    open: bool,
+    settings: analysis::Settings,
+    report: Option<analysis::Report>,
+}
+
+/// A level whose song is still being fetched
+struct PendingLoad {
+    level: gd::Level,
+    saved: Option<GdlData>, // recovered or saved project
+    fetch: fetch::Fetch,
+    progress: (u64, Option<u64>), // bytes downloaded, out of
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Color {
     Orange,
     Yellow,
@@ -42,6 +100,30 @@ enum Message {
     LevelSelected(usize),
     CloseError,
     LoadLevel,
+    SaveProject,
+    RestoreSession,
+    DiscardRecovery,
+    ToggleSettings,
+    SaveSettings,
+    RefreshSongInfo,
+    ReloadSong,
+    ToggleTransfer,
+    ImportMidi,
+    ExportMidi,
+    ImportOsu,
+    ImportLabels,
+    ExportLabels,
+    ImportGmd,
+    ExportGmd,
+    ToggleCopyLines,
+    CopyLines,
+    ToggleAnalysis,
+    Analyze,
+    Quantize,
+    ToggleCalibration,
+    DownloadProgress { done: u64, total: Option<u64> },
+    SongFetched(Result<fetch::SongFile, SongError>),
+    CancelLoad,
 }
 
 enum EditorMode {
@@ -59,20 +141,61 @@ struct Editor {
 struct WizardEditor {
     state: EditorState,
     data: WizardData,
+    quantize: QuantizeSettings,
+}
+
+/// The steady tempo lines are quantized to, unless the wizard has a tempo map of its own
+struct QuantizeSettings {
+    bpm: f32,
+    offset: f64, // seconds to a bar line; negative if it falls before the song starts
+    signature: (u32, u32),
+    tolerance: f64, // seconds
+    strength: f32,
 }
 
 struct EditorState {
     scroll_pos: f64,
     pts_per_second: f64, // zoom level
-    subdivisions: u32,
+    snap: music::Subdivision,
+    view_width: f64, // width of the editor rows, as of the last frame
+    selection: Option<(time::Duration, time::Duration)>,
+    dragged: Option<music::BeatPosition>, // marker being dragged
+    adjust_carry: f32, // ctrl+drag distance not yet turned into adjustment steps
+    pattern: String, // for the guideline generator, e.g. "x.x."
+    pattern_color: Color,
+    custom_song_path: String, // being typed in, before it's attached
+    tap_mode: tap::TapMode,
+    taps: Vec<time::Duration>, // for tap tempo and calibration
+    tap_color: Color,
+    tap_snap: bool,
+}
+
+/// What a click or drag on a row of beat-positioned markers asks for
+enum MarkerEdit {
+    Add(music::BeatPosition),
+    Remove(music::BeatPosition),
+    Move { from: music::BeatPosition, to: music::BeatPosition },
+    /// ctrl+drag up (positive) or down on a marker
+    Adjust { at: music::BeatPosition, steps: i32, fine: bool },
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 struct GdlData {
     green_lines: music::Lines,
     orange_lines: music::Lines,
     yellow_lines: music::Lines,
     beat_rate: music::BeatRate,
     time_signatures: music::TimeSignature,
+    swing: music::Swing,
+    #[serde(default)]
+    custom_song: Option<CustomSong>,
+}
+
+/// A local audio file used in place of the level's song while editing
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CustomSong {
+    path: std::path::PathBuf,
+    offset: f64, // seconds into the file where the level's song starts; negative if it starts late
 }
 
 struct WizardData {
@@ -81,46 +204,70 @@ struct WizardData {
     yellow_lines: music::Lines<time::Duration>,
     beat_rate: Option<music::BeatRate>,
     time_signatures: Option<music::TimeSignature>,
+    swing: Option<music::Swing>,
 }
 
 struct Song {
     name: String,
+    artist: String,
     id: i64,
-    source: rodio::source::Buffered<rodio::Decoder<File>>,
-    stream: rodio::OutputStream,
-    sink: rodio::Sink,
-    playing: bool,
+    player: playback::Player,
 }
 
+/// Lines quantizing left where they were
+#[derive(Error, Debug)]
+#[error("{} lines were too far from the grid to snap: {}", .0.len(), list_times(.0))]
+struct UnsnappedLines(Vec<time::Duration>);
+
 #[derive(Error, Debug)]
 enum SongError {
-    #[error("Not a Newgrounds song")]
-    NotNewgrounds,
+    #[error("Level has no song set")]
+    UnknownSong,
+    #[error("Official song {0} doesn't exist")]
+    UnknownOfficialSong(i64),
+    #[error("Couldn't open {0}; check the Resources folder in the settings")]
+    MissingOfficialFile(String),
+    #[error("Couldn't open custom audio file {0}")]
+    MissingCustomFile(String),
     #[error("Song mp3 couldn't be downloaded")]
     MissingFile(#[from] std::io::Error),
-    #[error("Couldn't decode mp3 file")]
-    BrokenSong(#[from] rodio::decoder::DecoderError),
-    #[error("Couldn't access song data on servers")]
+    #[error("{0}")]
+    BrokenSong(#[from] audio::TrackError),
+    #[error("Couldn't access song data on servers: {0}")]
     GdServerError(#[from] gd::SongRequestError),
     #[error("Couldn't fetch song from Newgrounds")]
     NgServerError(#[from] reqwest::Error),
     #[error("Missing download link")]
     MissingLink,
-    #[error("Unable to create audio stream")]
-    StreamError(#[from] rodio::StreamError),
-    #[error("Unable to create audio sink")]
-    SinkError(#[from] rodio::PlayError),
+    #[error("Song download was cancelled")]
+    Cancelled,
+    #[error("Song download stopped early ({received} of {expected} bytes)")]
+    IncompleteDownload { received: u64, expected: u64 },
+    #[error("Downloaded song is {actual:.2} MB, but should be {expected:.2} MB")]
+    SizeMismatch { actual: f64, expected: f64 },
 }
 
 struct BeatRateWidget<'a> {
     state: &'a mut EditorState,
     beat_rate: Option<&'a mut music::BeatRate>,
+    time_signatures: Option<&'a music::TimeSignature>, // for snapping
+    swing: Option<&'a music::Swing>, // for snapping
     song: &'a Song, // for waveform
 }
 
 struct TimeSignatureWidget<'a> {
     state: &'a mut EditorState,
     time_signatures: Option<&'a mut music::TimeSignature>,
+    beat_rate: Option<&'a music::BeatRate>, // to place beat-based changes
+    swing: Option<&'a music::Swing>, // for snapping
+    song: &'a Song, // for waveform
+}
+
+struct SwingWidget<'a> {
+    state: &'a mut EditorState,
+    swing: Option<&'a mut music::Swing>,
+    beat_rate: Option<&'a music::BeatRate>, // to place beat-based changes
+    time_signatures: Option<&'a music::TimeSignature>, // for snapping
     song: &'a Song, // for waveform
 }
 
@@ -129,16 +276,312 @@ where T: Ord
 {
     state: &'a mut EditorState,
     lines: &'a mut music::Lines<T>,
+    beat_rate: Option<&'a music::BeatRate>, // to place beat-based lines
+    time_signatures: Option<&'a music::TimeSignature>, // for snapping
+    swing: Option<&'a music::Swing>, // for snapping
     color: Color,
     song: &'a Song, // for waveform
 }
 
+/// Whole-song strip showing where the lines are and which part is in view
+struct OverviewWidget<'a> {
+    state: &'a mut EditorState,
+    line_times: Vec<time::Duration>,
+    song: &'a Song,
+}
+
 fn allocate_editor_space(ui: &mut egui::Ui) -> (egui::Rect, egui::Response) {
     let max_rect = ui.max_rect();
     let preferred_size = egui::Vec2::new(max_rect.size().x, 60.0);
     ui.allocate_exact_size(preferred_size, egui::Sense::click_and_drag())
 }
 
+fn playback_controls(ui: &mut egui::Ui, song: &mut Song) {
+    let mut speed = song.speed();
+    let mut mode = song.stretch_mode();
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::Slider::new(&mut speed, audio::MIN_SPEED..=audio::MAX_SPEED)
+                .text("Speed")
+                .suffix("x"),
+        );
+        egui::ComboBox::from_id_source("stretch_mode")
+            .selected_text(mode.label())
+            .show_ui(ui, |ui| {
+                for option in [audio::StretchMode::PreservePitch, audio::StretchMode::Resample] {
+                    ui.selectable_value(&mut mode, option, option.label());
+                }
+            });
+        if song.player.silent() {
+            ui.weak("no audio output");
+        }
+    });
+    song.set_speed(speed, mode);
+}
+
+fn snap_controls(ui: &mut egui::Ui, state: &mut EditorState) {
+    use music::SubdivisionKind;
+    ui.horizontal(|ui| {
+        ui.label("Snap");
+        egui::ComboBox::from_id_source("snap_note")
+            .selected_text(format!("1/{}", state.snap.note))
+            .show_ui(ui, |ui| {
+                for note in [1, 2, 4, 8, 16, 32] {
+                    ui.selectable_value(&mut state.snap.note, note, format!("1/{note}"));
+                }
+            });
+        egui::ComboBox::from_id_source("snap_kind")
+            .selected_text(state.snap.kind.to_string())
+            .show_ui(ui, |ui| {
+                for kind in [
+                    SubdivisionKind::Straight,
+                    SubdivisionKind::Tuplet { notes: 3, in_space_of: 2 },
+                    SubdivisionKind::Tuplet { notes: 5, in_space_of: 4 },
+                    SubdivisionKind::Dotted,
+                ] {
+                    ui.selectable_value(&mut state.snap.kind, kind, kind.to_string());
+                }
+                let custom = SubdivisionKind::Tuplet { notes: 7, in_space_of: 4 };
+                ui.selectable_value(&mut state.snap.kind, custom, "custom tuplet");
+            });
+        if let SubdivisionKind::Tuplet { notes, in_space_of } = &mut state.snap.kind {
+            ui.add(egui::DragValue::new(notes).clamp_range(2..=32));
+            ui.label(":");
+            ui.add(egui::DragValue::new(in_space_of).clamp_range(1..=32));
+        }
+        ui.weak("hold Alt to place freely");
+    });
+}
+
+fn generator_controls(ui: &mut egui::Ui, editor: &mut Editor, song: &Song) {
+    ui.horizontal(|ui| {
+        ui.label("Pattern");
+        ui.add(egui::TextEdit::singleline(&mut editor.state.pattern).desired_width(80.0))
+            .on_hover_text("x for a line, . for a rest; one character per snap step, restarting every bar");
+        egui::ComboBox::from_id_source("pattern_color")
+            .selected_text(format!("{:?}", editor.state.pattern_color))
+            .show_ui(ui, |ui| {
+                for color in [Color::Green, Color::Yellow, Color::Orange] {
+                    ui.selectable_value(&mut editor.state.pattern_color, color, format!("{color:?}"));
+                }
+            });
+        let target = if editor.state.selection.is_some() { "selection" } else { "song" };
+        if ui.button(format!("Fill {target}")).clicked() {
+            editor.generate_lines(song);
+        }
+    });
+}
+
+fn tap_controls(ui: &mut egui::Ui, editor: &mut Editor) {
+    use tap::TapMode;
+    ui.horizontal(|ui| {
+        ui.label("Tap (T)");
+        let mode = editor.state.tap_mode;
+        egui::ComboBox::from_id_source("tap_mode")
+            .selected_text(match mode {
+                TapMode::Off => "off",
+                TapMode::Tempo => "tempo",
+                TapMode::Place => "place lines",
+                TapMode::Calibrate => "calibrating",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut editor.state.tap_mode, TapMode::Off, "off");
+                ui.selectable_value(&mut editor.state.tap_mode, TapMode::Tempo, "tempo");
+                ui.selectable_value(&mut editor.state.tap_mode, TapMode::Place, "place lines");
+            });
+        if editor.state.tap_mode != mode {
+            editor.state.taps.clear();
+        }
+        match editor.state.tap_mode {
+            TapMode::Tempo => {
+                match tap::estimate_tempo(&editor.state.taps) {
+                    Some(tempo) => ui.label(format!(
+                        "{:.2} bpm, first beat at {:.3} s",
+                        tempo.bpm(),
+                        tempo.first_beat.as_secs_f64()
+                    )),
+                    None => ui.weak(format!("{} of {} taps", editor.state.taps.len(), tap::MIN_TEMPO_TAPS)),
+                };
+                let ready = editor.state.taps.len() >= tap::MIN_TEMPO_TAPS;
+                if ui.add_enabled(ready, egui::Button::new("Apply tempo")).clicked() {
+                    editor.apply_tap_tempo();
+                }
+                if ui.button("Clear taps").clicked() {
+                    editor.state.taps.clear();
+                }
+            }
+            TapMode::Place => {
+                egui::ComboBox::from_id_source("tap_color")
+                    .selected_text(format!("{:?}", editor.state.tap_color))
+                    .show_ui(ui, |ui| {
+                        for color in [Color::Green, Color::Yellow, Color::Orange] {
+                            ui.selectable_value(&mut editor.state.tap_color, color, format!("{color:?}"));
+                        }
+                    });
+                ui.checkbox(&mut editor.state.tap_snap, "snap");
+            }
+            TapMode::Off | TapMode::Calibrate => {}
+        }
+    });
+}
+
+/// A checkbox and a value; unchecked stands for "any"
+fn optional_value<T: egui::emath::Numeric>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut Option<T>,
+    range: std::ops::RangeInclusive<T>,
+) {
+    let mut enabled = value.is_some();
+    ui.checkbox(&mut enabled, label);
+    match (enabled, value.as_mut()) {
+        (true, Some(value)) => {
+            ui.add(egui::DragValue::new(value).clamp_range(range));
+        }
+        (true, None) => *value = Some(*range.start()),
+        (false, _) => *value = None,
+    }
+}
+
+/// A level's guidelines, taken from its Pipedash project if it has one since that may be newer
+fn guidelines_of(level: &gd::Level) -> Result<gd::RawLinesTriplet, project::ProjectError> {
+    let project = project::load(&level.into())?;
+    Ok(preferred_guidelines(project.as_ref(), || level.load_inner().get_lines()))
+}
+
+/// The project's lines if there is a project, otherwise the `saved` ones
+fn preferred_guidelines(project: Option<&GdlData>, saved: impl FnOnce() -> gd::RawLinesTriplet) -> gd::RawLinesTriplet {
+    match project {
+        Some(data) => data.raw_lines(),
+        None => saved(),
+    }
+}
+
+fn midi_mapping_controls(ui: &mut egui::Ui, mappings: &mut Vec<midi::NoteMapping>) {
+    let mut removed = None;
+    for (idx, mapping) in mappings.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source(("midi_color", idx))
+                .selected_text(format!("{:?}", mapping.color))
+                .show_ui(ui, |ui| {
+                    for color in [Color::Green, Color::Yellow, Color::Orange] {
+                        ui.selectable_value(&mut mapping.color, color, format!("{color:?}"));
+                    }
+                });
+            ui.label("from notes on");
+            optional_value(ui, "track", &mut mapping.track, 0..=255);
+            optional_value(ui, "key", &mut mapping.key, 0..=127);
+            if ui.small_button("x").clicked() {
+                removed = Some(idx);
+            }
+        });
+    }
+    if let Some(idx) = removed {
+        mappings.remove(idx);
+    }
+    ui.horizontal(|ui| {
+        if ui.button("Add mapping").clicked() {
+            mappings.push(midi::NoteMapping { track: None, key: None, color: Color::Green });
+        }
+        if ui.button("Pipedash export layout").clicked() {
+            *mappings = midi::NoteMapping::exported();
+        }
+    });
+}
+
+fn list_times(times: &[time::Duration]) -> String {
+    times.iter().map(|time| format!("{:.3}s", time.as_secs_f64())).collect::<Vec<_>>().join(", ")
+}
+
+/// Returns whether to quantize
+fn quantize_controls(ui: &mut egui::Ui, editor: &mut WizardEditor) -> bool {
+    let settings = &mut editor.quantize;
+    match (&editor.data.beat_rate, &editor.data.time_signatures) {
+        (Some(_), Some(_)) => {
+            ui.label("Quantizing to the wizard's tempo map");
+        }
+        _ => {
+            ui.horizontal(|ui| {
+                ui.label("Tempo");
+                ui.add(egui::DragValue::new(&mut settings.bpm).speed(0.1).clamp_range(1.0..=1000.0).suffix(" bpm"));
+                ui.label("first bar at");
+                ui.add(egui::DragValue::new(&mut settings.offset).speed(0.001).max_decimals(3).suffix(" s"));
+                ui.label("in");
+                ui.add(egui::DragValue::new(&mut settings.signature.0).clamp_range(1..=64));
+                ui.label("/");
+                ui.add(egui::DragValue::new(&mut settings.signature.1).clamp_range(1..=64));
+            });
+        }
+    }
+    snap_controls(ui, &mut editor.state);
+    let mut quantize = false;
+    ui.horizontal(|ui| {
+        ui.label("Tolerance");
+        ui.add(egui::DragValue::new(&mut settings.tolerance).speed(0.001).clamp_range(0.0..=1.0).suffix(" s"));
+        ui.add(egui::Slider::new(&mut settings.strength, 0.0..=1.0).text("strength"));
+        quantize = ui.button("Quantize").clicked();
+    });
+    quantize
+}
+
+/// Returns whether the song has to be reloaded
+fn custom_song_controls(ui: &mut egui::Ui, editor: &mut Editor) -> bool {
+    let mut reload = false;
+    ui.horizontal(|ui| {
+        ui.label("Custom audio");
+        match &mut editor.data.custom_song {
+            Some(custom) => {
+                ui.label(custom.path.display().to_string());
+                ui.add(egui::DragValue::new(&mut custom.offset).speed(0.001).max_decimals(3).suffix(" s"))
+                    .on_hover_text("How far into the file the level's song starts; negative if the file starts late");
+                if ui.button("Apply offset").clicked() {
+                    reload = true;
+                }
+                if ui.button("Detach").clicked() {
+                    editor.data.custom_song = None;
+                    reload = true;
+                }
+            }
+            None => {
+                ui.add(egui::TextEdit::singleline(&mut editor.state.custom_song_path).hint_text("path to mp3, ogg, wav or flac"));
+                if ui
+                    .add_enabled(!editor.state.custom_song_path.is_empty(), egui::Button::new("Attach"))
+                    .clicked()
+                {
+                    editor.data.custom_song = Some(CustomSong {
+                        path: mem::take(&mut editor.state.custom_song_path).into(),
+                        offset: 0.0,
+                    });
+                    reload = true;
+                }
+            }
+        }
+    });
+    reload
+}
+
+fn view_controls(ui: &mut egui::Ui, state: &mut EditorState, song: &Song) {
+    ui.horizontal(|ui| {
+        if ui.button("Zoom to fit").clicked() {
+            state.zoom_to_fit(song.length());
+        }
+        if ui
+            .add_enabled(state.selection.is_some(), egui::Button::new("Zoom to selection"))
+            .clicked()
+        {
+            state.zoom_to_selection(song.length());
+        }
+    });
+}
+
+/// Draws the playhead over an editor row, if it is in view
+fn draw_playhead(ui: &egui::Ui, rect: egui::Rect, state: &EditorState, song: &Song) {
+    let x = state.time_to_x(rect, song.position());
+    if rect.x_range().contains(&x) {
+        ui.painter().vline(x, rect.y_range(), egui::Stroke::new(1.0, eframe::epaint::Color32::WHITE));
+    }
+}
+
 impl From<Color> for eframe::epaint::Color32 {
     fn from(rhs: Color) -> Self {
         match rhs {
@@ -150,17 +593,27 @@ impl From<Color> for eframe::epaint::Color32 {
 }
 
 impl EditorMode {
-    pub fn display(&mut self, ui: &mut egui::Ui) {
+    pub fn display(&mut self, ui: &mut egui::Ui, config: &config::Config) {
         let ctx = ui.ctx();
         match self {
             EditorMode::RhythmWizard { editor, song } => {
                 ui.label("Rhythm Wizard");
             },
             EditorMode::Full { editor, song } => {
-                editor.handle_keyboard_input(ctx, song);
+                editor.handle_keyboard_input(ctx, song, config.tap_latency);
+                if song.playing() {
+                    ctx.request_repaint();
+                }
                 ui.label("Editor");
+                playback_controls(ui, song);
+                view_controls(ui, &mut editor.state, song);
+                snap_controls(ui, &mut editor.state);
+                generator_controls(ui, editor, song);
+                tap_controls(ui, editor);
+                ui.add(editor.overview_widget(song));
                 ui.add(editor.time_signature_widget(song));
                 ui.add(editor.beat_rate_widget(song));
+                ui.add(editor.swing_widget(song));
                 ui.add(editor.lines_widget(Color::Green, song));
                 ui.add(editor.lines_widget(Color::Orange, song));
                 ui.add(editor.lines_widget(Color::Yellow, song));
@@ -174,7 +627,206 @@ impl EditorMode {
 
 impl Default for EditorState {
     fn default() -> Self {
-        EditorState { scroll_pos: 0.0, pts_per_second: 10.0, subdivisions: 4 }
+        EditorState {
+            scroll_pos: 0.0,
+            pts_per_second: 10.0,
+            snap: Default::default(),
+            view_width: 0.0,
+            selection: None,
+            dragged: None,
+            adjust_carry: 0.0,
+            pattern: "x.x.".into(),
+            pattern_color: Color::Green,
+            custom_song_path: String::new(),
+            tap_mode: tap::TapMode::Off,
+            taps: Vec::new(),
+            tap_color: Color::Green,
+            tap_snap: false,
+        }
+    }
+}
+
+impl EditorState {
+    const MIN_ZOOM: f64 = 1.0;
+    const MAX_ZOOM: f64 = 2000.0;
+    /// how close (in points) the pointer must be to pick up a marker
+    const GRAB_DISTANCE: f32 = 5.0;
+    /// ctrl+drag distance (in points) per adjustment step
+    const ADJUST_STEP: f32 = 4.0;
+
+    fn time_to_x(&self, rect: egui::Rect, time: time::Duration) -> f32 {
+        rect.left() + (time.as_secs_f64() * self.pts_per_second - self.scroll_pos) as f32
+    }
+
+    fn x_to_time(&self, rect: egui::Rect, x: f32) -> time::Duration {
+        time::Duration::from_secs_f64(
+            ((x - rect.left()) as f64 + self.scroll_pos).max(0.0) / self.pts_per_second,
+        )
+    }
+
+    fn scroll_by(&mut self, pts: f64, song_length: time::Duration) {
+        self.scroll_pos += pts;
+        self.clamp_scroll(song_length);
+    }
+
+    fn clamp_scroll(&mut self, song_length: time::Duration) {
+        let song_width = song_length.as_secs_f64() * self.pts_per_second;
+        self.scroll_pos = self.scroll_pos.clamp(0.0, (song_width - self.view_width).max(0.0));
+    }
+
+    /// Zooms by `factor`, keeping the time under `anchor` (points from the left edge) in place
+    fn zoom_around(&mut self, factor: f64, anchor: f64, song_length: time::Duration) {
+        let anchored_time = (self.scroll_pos + anchor) / self.pts_per_second;
+        self.pts_per_second = (self.pts_per_second * factor).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.scroll_pos = anchored_time * self.pts_per_second - anchor;
+        self.clamp_scroll(song_length);
+    }
+
+    fn zoom_to_range(&mut self, start: time::Duration, end: time::Duration, song_length: time::Duration) {
+        let span = (end - start).as_secs_f64();
+        if self.view_width <= 0.0 || span <= 0.0 {
+            return;
+        }
+        self.pts_per_second = (self.view_width / span).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.scroll_pos = start.as_secs_f64() * self.pts_per_second;
+        self.clamp_scroll(song_length);
+    }
+
+    fn zoom_to_fit(&mut self, song_length: time::Duration) {
+        self.zoom_to_range(time::Duration::ZERO, song_length, song_length);
+    }
+
+    fn zoom_to_selection(&mut self, song_length: time::Duration) {
+        if let Some((start, end)) = self.selection {
+            self.zoom_to_range(start, end, song_length);
+        }
+    }
+
+    /// Scrolling, zooming and selecting shared by all editor rows
+    fn handle_view_input(&mut self, ui: &egui::Ui, rect: egui::Rect, res: &egui::Response, song: &Song) {
+        self.view_width = rect.width() as f64;
+        let input = ui.input();
+
+        if res.hovered() {
+            let zoom = input.zoom_delta() as f64;
+            if zoom != 1.0 {
+                let anchor = input.pointer.hover_pos().map_or(0.0, |pos| pos.x - rect.left());
+                self.zoom_around(zoom, anchor as f64, song.length());
+            }
+            let wheel = input.scroll_delta;
+            if wheel != egui::Vec2::ZERO {
+                self.scroll_by(-(wheel.x + wheel.y) as f64, song.length());
+            }
+        }
+
+        if res.dragged_by(egui::PointerButton::Middle) {
+            self.scroll_by(-res.drag_delta().x as f64, song.length());
+        } else if res.dragged_by(egui::PointerButton::Primary) && input.modifiers.shift {
+            if let (Some(origin), Some(current)) = (input.pointer.press_origin(), res.interact_pointer_pos()) {
+                let (a, b) = (self.x_to_time(rect, origin.x), self.x_to_time(rect, current.x));
+                self.selection = Some((a.min(b), a.max(b).min(song.length())));
+            }
+        } else if res.clicked() && input.modifiers.shift {
+            self.selection = None;
+        }
+    }
+
+    fn beat_to_x(&self, rect: egui::Rect, beat: music::BeatPosition, beat_rate: &music::BeatRate) -> f32 {
+        self.time_to_x(rect, beat_rate.time_at(beat))
+    }
+
+    /// Beats from the left to the right edge of `rect`
+    fn visible_beats(&self, rect: egui::Rect, beat_rate: &music::BeatRate) -> (music::BeatPosition, music::BeatPosition) {
+        (
+            beat_rate.beat_at(self.x_to_time(rect, rect.left())),
+            beat_rate.beat_at(self.x_to_time(rect, rect.right())),
+        )
+    }
+
+    /// Marker closest to `x`, if it is close enough to grab
+    fn marker_near(
+        &self,
+        rect: egui::Rect,
+        x: f32,
+        markers: impl IntoIterator<Item = music::BeatPosition>,
+        beat_rate: &music::BeatRate,
+    ) -> Option<music::BeatPosition> {
+        markers
+            .into_iter()
+            .map(|marker| (marker, (self.beat_to_x(rect, marker, beat_rate) - x).abs()))
+            .filter(|&(_, distance)| distance <= Self::GRAB_DISTANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(marker, _)| marker)
+    }
+
+    /// Turns clicks and drags on a row of markers into an edit, snapping new positions to the
+    /// (swung) grid unless Alt is held
+    fn marker_edit(
+        &mut self,
+        ui: &egui::Ui,
+        rect: egui::Rect,
+        res: &egui::Response,
+        markers: impl IntoIterator<Item = music::BeatPosition>,
+        beat_rate: &music::BeatRate,
+        time_signatures: &music::TimeSignature,
+        swing: Option<&music::Swing>,
+    ) -> Option<MarkerEdit> {
+        let input = ui.input();
+        if res.drag_released() {
+            self.dragged = None;
+        }
+        // shift is for selecting, middle button for scrolling
+        if input.modifiers.shift || res.dragged_by(egui::PointerButton::Middle) {
+            return None;
+        }
+        let pointer = res.interact_pointer_pos()?;
+        let target = {
+            let beat = beat_rate.beat_at(self.x_to_time(rect, pointer.x));
+            match swing {
+                _ if input.modifiers.alt => beat,
+                Some(swing) => swing.snap(beat, self.snap, time_signatures),
+                None => time_signatures.snap(beat, self.snap),
+            }
+        };
+
+        if res.drag_started() {
+            let origin = input.pointer.press_origin().unwrap_or(pointer);
+            self.dragged = self.marker_near(rect, origin.x, markers, beat_rate);
+            self.adjust_carry = 0.0;
+            None
+        } else if res.dragged_by(egui::PointerButton::Primary) {
+            let from = self.dragged?;
+            if input.modifiers.command {
+                self.adjust_carry -= res.drag_delta().y;
+                let steps = (self.adjust_carry / Self::ADJUST_STEP).trunc();
+                self.adjust_carry -= steps * Self::ADJUST_STEP;
+                (steps != 0.0).then_some(MarkerEdit::Adjust { at: from, steps: steps as i32, fine: input.modifiers.alt })
+            } else {
+                (target != from).then_some(MarkerEdit::Move { from, to: target })
+            }
+        } else if res.clicked() {
+            Some(match self.marker_near(rect, pointer.x, markers, beat_rate) {
+                Some(marker) => MarkerEdit::Remove(marker),
+                None => MarkerEdit::Add(target),
+            })
+        } else if res.secondary_clicked() {
+            self.marker_near(rect, pointer.x, markers, beat_rate).map(MarkerEdit::Remove)
+        } else {
+            None
+        }
+    }
+
+    fn draw_selection(&self, ui: &egui::Ui, rect: egui::Rect) {
+        if let Some((start, end)) = self.selection {
+            let selected = egui::Rect::from_x_y_ranges(
+                self.time_to_x(rect, start).max(rect.left())..=self.time_to_x(rect, end).min(rect.right()),
+                rect.y_range(),
+            );
+            if selected.width() > 0.0 {
+                ui.painter()
+                    .rect_filled(selected, 0.0, eframe::epaint::Color32::from_white_alpha(24));
+            }
+        }
     }
 }
 
@@ -186,66 +838,155 @@ impl Default for GdlData {
             yellow_lines: Default::default(),
             beat_rate: music::StaticBeatRate::from_bpm(120.0).into(),
             time_signatures: music::StaticTimeSignature::new(4, 4).into(),
+            swing: Default::default(),
+            custom_song: None,
         }
     }
 }
 
-impl Song {
-    pub fn try_new(gd_song: &gd::Song) -> Result<Self, SongError> {
-        if let &gd::Song::Newgrounds { id } = gd_song {
-            let song_result = gd_song.get_response();
-            let song_path = gd::save_path().join(format!("{id}.mp3"));
+impl Default for QuantizeSettings {
+    fn default() -> Self {
+        Self { bpm: 120.0, offset: 0.0, signature: (4, 4), tolerance: 0.05, strength: 1.0 }
+    }
+}
+
+impl Default for TransferWindow {
+    fn default() -> Self {
+        Self {
+            open: false,
+            path: String::new(),
+            midi_mappings: vec![midi::NoteMapping { track: None, key: None, color: Color::Green }],
+            osu_hit_objects: None,
+        }
+    }
+}
 
-            let (file, name) = match (File::open(&song_path), song_result) {
-                (Ok(file), response) => {
-                    let name = response
-                        .ok()
-                        .and_then(|response| response.name().map(Into::into))
-                        .unwrap_or("Missing Name".into());
+impl GdlData {
+    fn lines(&self, color: Color) -> &music::Lines {
+        match color {
+            Color::Green => &self.green_lines,
+            Color::Yellow => &self.yellow_lines,
+            Color::Orange => &self.orange_lines,
+        }
+    }
 
-                    (file, name)
-                }
-                (Err(_), Ok(response)) => {
-                    let song_blob = response
-                        .download_link()
-                        .ok_or(SongError::MissingLink)
-                        .and_then(|link| Ok(req::get(link)?.bytes()?))?;
+    fn lines_mut(&mut self, color: Color) -> &mut music::Lines {
+        match color {
+            Color::Green => &mut self.green_lines,
+            Color::Yellow => &mut self.yellow_lines,
+            Color::Orange => &mut self.orange_lines,
+        }
+    }
 
-                    let mut file = File::open(&song_path)?;
-                    file.write_all(&song_blob)?;
+    /// The lines at their times in the song
+    fn raw_lines(&self) -> gd::RawLinesTriplet {
+        let raw = |lines: &music::Lines| {
+            let mut raw = music::Lines::new();
+            for &pos in lines.get_positions() {
+                raw.insert(self.beat_rate.time_at(pos));
+            }
+            raw
+        };
+        gd::RawLinesTriplet {
+            orange: raw(&self.orange_lines),
+            yellow: raw(&self.yellow_lines),
+            green: raw(&self.green_lines),
+        }
+    }
 
-                    (file, response.name().unwrap_or_default().into())
-                }
-                (Err(err), Err(_)) => return Err(err.into()),
-            };
-            
-            let source = rodio::Decoder::new_mp3(file)?.buffered();
+    /// Replaces the lines with ones at the given times, placed on the current tempo map
+    fn set_raw_lines(&mut self, raw: gd::RawLinesTriplet) {
+        self.orange_lines = music::Lines::new();
+        self.yellow_lines = music::Lines::new();
+        self.green_lines = music::Lines::new();
+        self.add_raw_lines(raw);
+    }
 
-            let (stream, stream_handle) = rodio::OutputStream::try_default()?;
-            let sink = rodio::Sink::try_new(&stream_handle)?;
+    /// Adds lines at the given times, placed on the current tempo map
+    fn add_raw_lines(&mut self, raw: gd::RawLinesTriplet) {
+        for (color, raw) in [(Color::Orange, raw.orange), (Color::Yellow, raw.yellow), (Color::Green, raw.green)] {
+            for &time in raw.get_positions() {
+                let pos = self.beat_rate.beat_at(time);
+                self.lines_mut(color).insert(pos);
+            }
+        }
+    }
+}
 
-            Ok(Self { name, id, source, stream, sink, playing: false })
-        } else {
-            Err(SongError::NotNewgrounds)
+impl WizardData {
+    fn raw_lines(&self) -> gd::RawLinesTriplet {
+        gd::RawLinesTriplet {
+            orange: self.orange_lines.clone(),
+            yellow: self.yellow_lines.clone(),
+            green: self.green_lines.clone(),
+        }
+    }
+
+    fn set_raw_lines(&mut self, raw: gd::RawLinesTriplet) {
+        self.orange_lines = raw.orange;
+        self.yellow_lines = raw.yellow;
+        self.green_lines = raw.green;
+    }
+
+    fn add_raw_lines(&mut self, raw: gd::RawLinesTriplet) {
+        for (lines, raw) in [
+            (&mut self.orange_lines, raw.orange),
+            (&mut self.yellow_lines, raw.yellow),
+            (&mut self.green_lines, raw.green),
+        ] {
+            for &time in raw.get_positions() {
+                lines.insert(time);
+            }
         }
     }
+}
+
+impl Song {
+    /// Decodes the song; with `audio` off (or no audio device) it plays silently
+    pub fn try_new(song_file: fetch::SongFile, audio: bool) -> Result<Self, SongError> {
+        let fetch::SongFile { file, name, artist, id, custom_offset } = song_file;
+        let track = audio::Track::open(file, custom_offset.unwrap_or(0.0))?;
+
+        Ok(Self {
+            name,
+            artist,
+            id,
+            player: playback::Player::with_default_output(track, audio),
+        })
+    }
 
     pub fn length(&self) -> time::Duration {
-        self.source.total_duration().expect("the source should have a well-defined duration")
+        self.player.length()
     }
 
-    pub fn play_from(&mut self, position: time::Duration) {
-        self.playing = true;
-        self.sink.append(self.source.clone().skip_duration(position))
+    /// Plays on from the playhead, starting over once the song has ended
+    pub fn resume(&mut self) {
+        self.player.resume()
     }
 
     pub fn stop(&mut self) {
-        self.playing = false;
-        self.sink.stop();
+        self.player.stop()
     }
 
     pub fn playing(&self) -> bool {
-        self.playing
+        self.player.playing()
+    }
+
+    /// Position of the playhead in song time, regardless of playback speed
+    pub fn position(&self) -> time::Duration {
+        self.player.position()
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.player.speed()
+    }
+
+    pub fn stretch_mode(&self) -> audio::StretchMode {
+        self.player.stretch_mode()
+    }
+
+    pub fn set_speed(&mut self, speed: f32, mode: audio::StretchMode) {
+        self.player.set_speed(speed, mode)
     }
 }
 
@@ -254,6 +995,8 @@ impl Editor {
         BeatRateWidget {
             state: &mut self.state,
             beat_rate: Some(&mut self.data.beat_rate),
+            time_signatures: Some(&self.data.time_signatures),
+            swing: Some(&self.data.swing),
             song,
         }
     }
@@ -262,6 +1005,18 @@ impl Editor {
         TimeSignatureWidget {
             state: &mut self.state,
             time_signatures: Some(&mut self.data.time_signatures),
+            beat_rate: Some(&self.data.beat_rate),
+            swing: Some(&self.data.swing),
+            song,
+        }
+    }
+
+    pub fn swing_widget<'a>(&'a mut self, song: &'a mut Song) -> SwingWidget {
+        SwingWidget {
+            state: &mut self.state,
+            swing: Some(&mut self.data.swing),
+            beat_rate: Some(&self.data.beat_rate),
+            time_signatures: Some(&self.data.time_signatures),
             song,
         }
     }
@@ -274,11 +1029,44 @@ impl Editor {
                 Color::Yellow => &mut self.data.yellow_lines,
                 Color::Orange => &mut self.data.orange_lines,
             },
+            beat_rate: Some(&self.data.beat_rate),
+            time_signatures: Some(&self.data.time_signatures),
+            swing: Some(&self.data.swing),
             color: col,
             song,
         }
     }
 
+    pub fn overview_widget<'a>(&'a mut self, song: &'a Song) -> OverviewWidget {
+        let data = &self.data;
+        OverviewWidget {
+            line_times: [&data.green_lines, &data.orange_lines, &data.yellow_lines]
+                .into_iter()
+                .flat_map(|lines| lines.get_positions())
+                .map(|&pos| data.beat_rate.time_at(pos))
+                .collect(),
+            state: &mut self.state,
+            song,
+        }
+    }
+
+    /// Fills the selection (or the whole song) with the pattern from the generator controls
+    fn generate_lines(&mut self, song: &Song) {
+        let (start, end) = self.state.selection.unwrap_or((time::Duration::ZERO, song.length()));
+        let pattern = music::Pattern::parse(self.state.snap, &self.state.pattern);
+        let data = &mut self.data;
+        let positions = pattern.generate(
+            data.beat_rate.beat_at(start),
+            data.beat_rate.beat_at(end),
+            &data.time_signatures,
+            &data.swing,
+        );
+        let lines = data.lines_mut(self.state.pattern_color);
+        positions.into_iter().for_each(|pos| {
+            lines.insert(pos);
+        });
+    }
+
     /// points in width of entire song
     fn song_width(&self, song: &Song) -> f64 {
         song.length().as_secs_f64() * self.state.pts_per_second
@@ -290,27 +1078,80 @@ impl Editor {
             song.stop();
         } else {
             println!("starting");
-            song.play_from(time::Duration::default());
+            song.resume();
         }
         // todo!("toggle song playback")
     }
 
-    fn handle_keyboard_input(&mut self, ctx: &egui::Context, song: &mut Song) {
+    fn handle_keyboard_input(&mut self, ctx: &egui::Context, song: &mut Song, tap_latency: f64) {
         use egui::Key;
         use egui::Event;
+        // the shortcuts would fire while typing into a text field
+        if ctx.wants_keyboard_input() {
+            return;
+        }
         ctx.input().events
             .iter()
             .for_each(|ev| match ev {
                 Event::Key { key: Key::ArrowLeft, pressed: true, modifiers } => self.scroll(-5.0, song),
                 Event::Key { key: Key::ArrowRight, pressed: true, modifiers } => self.scroll(5.0, song),
                 Event::Key { key: Key::Space, pressed: true, modifiers } if modifiers.is_none() => self.play_pause(song),
+                Event::Key { key: Key::F, pressed: true, modifiers } if modifiers.is_none() => self.state.zoom_to_fit(song.length()),
+                Event::Key { key: Key::Z, pressed: true, modifiers } if modifiers.is_none() => self.state.zoom_to_selection(song.length()),
+                Event::Key { key: Key::T, pressed: true, modifiers } if modifiers.is_none() => self.tap(song, tap_latency),
                 _ => (),
             });
     }
 
     fn scroll(&mut self, pts: f64, song: &Song) {
-        self.state.scroll_pos += pts;
-        self.state.scroll_pos.clamp(0f64, self.song_width(song));
+        self.state.scroll_by(pts, song.length());
+    }
+
+    /// Records a tap at the playhead, or places a line there, depending on the tap mode
+    fn tap(&mut self, song: &Song, latency: f64) {
+        if !song.playing() {
+            return;
+        }
+        let position = song.position();
+        match self.state.tap_mode {
+            tap::TapMode::Off => {}
+            tap::TapMode::Tempo => self.state.taps.push(tap::compensate(position, latency)),
+            // calibration measures the latency, so it mustn't be compensated for yet
+            tap::TapMode::Calibrate => self.state.taps.push(position),
+            tap::TapMode::Place => {
+                let data = &mut self.data;
+                let mut pos = data.beat_rate.beat_at(tap::compensate(position, latency));
+                if self.state.tap_snap {
+                    pos = data.swing.snap(pos, self.state.snap, &data.time_signatures);
+                }
+                data.lines_mut(self.state.tap_color).insert(pos);
+            }
+        }
+    }
+
+    /// Replaces the tempo map with a steady one fitted to the taps
+    fn apply_tap_tempo(&mut self) {
+        let Some(tempo) = tap::estimate_tempo(&self.state.taps) else {
+            return;
+        };
+        let signature = self.data.time_signatures.at_beat(0.0.into());
+        let (beat_rate, time_signatures) = music::steady_tempo(
+            music::StaticBeatRate::from_beat_length(tempo.beat_length),
+            signature,
+            tempo.first_beat.as_secs_f64(),
+        );
+        self.data.beat_rate = beat_rate;
+        self.data.time_signatures = time_signatures;
+        self.state.taps.clear();
+    }
+
+    /// Latency measured from calibration taps against the whole beats of the tempo map
+    fn measured_latency(&self) -> Option<f64> {
+        let beat_rate = &self.data.beat_rate;
+        tap::measure_latency(&self.state.taps, |tap| {
+            let beat = beat_rate.beat_at(tap);
+            beat_rate.time_at(beat.round().into())
+        })
     }
 
 }
@@ -320,6 +1161,8 @@ impl WizardEditor {
         BeatRateWidget {
             state: &mut self.state,
             beat_rate: self.data.beat_rate.as_mut(),
+            time_signatures: self.data.time_signatures.as_ref(),
+            swing: self.data.swing.as_ref(),
             song,
         }
     }
@@ -328,6 +1171,18 @@ impl WizardEditor {
         TimeSignatureWidget {
             state: &mut self.state,
             time_signatures: self.data.time_signatures.as_mut(),
+            beat_rate: self.data.beat_rate.as_ref(),
+            swing: self.data.swing.as_ref(),
+            song,
+        }
+    }
+
+    pub fn swing_widget<'a>(&'a mut self, song: &'a mut Song) -> SwingWidget {
+        SwingWidget {
+            state: &mut self.state,
+            swing: self.data.swing.as_mut(),
+            beat_rate: self.data.beat_rate.as_ref(),
+            time_signatures: self.data.time_signatures.as_ref(),
             song,
         }
     }
@@ -340,10 +1195,25 @@ impl WizardEditor {
                 Color::Yellow => &mut self.data.yellow_lines,
                 Color::Orange => &mut self.data.orange_lines,
             },
+            beat_rate: self.data.beat_rate.as_ref(),
+            time_signatures: self.data.time_signatures.as_ref(),
+            swing: self.data.swing.as_ref(),
             color: col,
             song,
         }
     }
+
+    pub fn overview_widget<'a>(&'a mut self, song: &'a Song) -> OverviewWidget {
+        let data = &self.data;
+        OverviewWidget {
+            line_times: [&data.green_lines, &data.orange_lines, &data.yellow_lines]
+                .into_iter()
+                .flat_map(|lines| lines.get_positions().iter().copied())
+                .collect(),
+            state: &mut self.state,
+            song,
+        }
+    }
 }
 
 impl From<gd::RawLinesTriplet> for WizardData {
@@ -354,13 +1224,14 @@ impl From<gd::RawLinesTriplet> for WizardData {
             yellow_lines: lines.yellow,
             beat_rate: None,
             time_signatures: None,
+            swing: None,
         }
     }
 }
 
 impl From<gd::RawLinesTriplet> for WizardEditor {
     fn from(lines: gd::RawLinesTriplet) -> Self {
-        Self { state: Default::default(), data: lines.into() }
+        Self { state: Default::default(), data: lines.into(), quantize: Default::default() }
     }
 }
 
@@ -368,10 +1239,60 @@ impl<'a> egui::Widget for BeatRateWidget<'a> {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
         let (rect, res) = allocate_editor_space(ui);
         // handle interactions
-        // draw widget
-        if ui.is_rect_visible(rect) {
+        self.state.handle_view_input(ui, rect, &res, self.song);
+        if let (Some(beat_rate), Some(time_signatures)) = (self.beat_rate, self.time_signatures) {
+            let changes = beat_rate.changes().keys().copied().collect::<Vec<_>>();
+            match self.state.marker_edit(ui, rect, &res, changes, beat_rate, time_signatures, self.swing) {
+                Some(MarkerEdit::Add(pos)) => beat_rate.add_change(pos, beat_rate.at_beat(pos)),
+                Some(MarkerEdit::Remove(pos)) => {
+                    beat_rate.remove_change(pos);
+                }
+                Some(MarkerEdit::Move { from, to }) if !beat_rate.changes().contains_key(&to) => {
+                    if let Some(rate) = beat_rate.remove_change(from) {
+                        beat_rate.add_change(to, rate);
+                        self.state.dragged = Some(to);
+                    }
+                }
+                Some(MarkerEdit::Adjust { at, steps, fine }) => {
+                    let step = if fine { 0.05 } else { 0.5 };
+                    let bpm = (beat_rate.at_beat(at).bpm() + steps as f32 * step).clamp(10.0, 1000.0);
+                    beat_rate.add_change(at, music::StaticBeatRate::from_bpm(bpm));
+                }
+                _ => (),
+            }
+            // draw widget
+            if ui.is_rect_visible(rect) {
+                let painter = ui.painter();
+                painter.rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(0));
+                let text_color = eframe::epaint::Color32::from_gray(200);
+                painter.text(
+                    rect.left_top(),
+                    egui::Align2::LEFT_TOP,
+                    format!("{:.2} BPM", beat_rate.at_beat(beat_rate.beat_at(self.state.x_to_time(rect, rect.left()))).bpm()),
+                    egui::FontId::proportional(10.0),
+                    text_color,
+                );
+                for (&pos, rate) in beat_rate.changes() {
+                    let x = self.state.beat_to_x(rect, pos, beat_rate);
+                    if rect.x_range().contains(&x) {
+                        painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, eframe::epaint::Color32::LIGHT_BLUE));
+                        painter.text(
+                            egui::pos2(x + 2.0, rect.center().y),
+                            egui::Align2::LEFT_CENTER,
+                            format!("{:.2}", rate.bpm()),
+                            egui::FontId::proportional(10.0),
+                            text_color,
+                        );
+                    }
+                }
+                self.state.draw_selection(ui, rect);
+                draw_playhead(ui, rect, self.state, self.song);
+            }
+        } else if ui.is_rect_visible(rect) {
             ui.painter()
                 .rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(0));
+            self.state.draw_selection(ui, rect);
+            draw_playhead(ui, rect, self.state, self.song);
         }
         res
     }
@@ -385,10 +1306,148 @@ impl<'a> egui::Widget for TimeSignatureWidget<'a> {
         // 2. allocate space
         let (rect, res) = ui.allocate_exact_size(preferred_size, egui::Sense::click_and_drag());
         // 3. handle interactions
-        // 4. draw widget
-        if ui.is_rect_visible(rect) {
+        self.state.handle_view_input(ui, rect, &res, self.song);
+        if let (Some(time_signatures), Some(beat_rate)) = (self.time_signatures, self.beat_rate) {
+            let changes = time_signatures.changes().keys().copied().collect::<Vec<_>>();
+            match self.state.marker_edit(ui, rect, &res, changes, beat_rate, time_signatures, self.swing) {
+                Some(MarkerEdit::Add(pos)) => time_signatures.add_change(pos, time_signatures.at_beat(pos)),
+                Some(MarkerEdit::Remove(pos)) => {
+                    time_signatures.remove_change(pos);
+                }
+                Some(MarkerEdit::Move { from, to }) if !time_signatures.changes().contains_key(&to) => {
+                    if let Some(signature) = time_signatures.remove_change(from) {
+                        time_signatures.add_change(to, signature);
+                        self.state.dragged = Some(to);
+                    }
+                }
+                Some(MarkerEdit::Adjust { at, steps, fine }) => {
+                    let signature = time_signatures.at_beat(at);
+                    let adjusted = if fine {
+                        // alt picks the beat unit instead of the beat count
+                        let denominator = (signature.denominator().ilog2() as i32 + steps).clamp(0, 5);
+                        music::StaticTimeSignature::new(signature.numerator(), 1 << denominator)
+                    } else {
+                        let numerator = (signature.numerator() as i32 + steps).clamp(1, 32);
+                        music::StaticTimeSignature::new(numerator as u32, signature.denominator())
+                    };
+                    time_signatures.add_change(at, adjusted);
+                }
+                _ => (),
+            }
+            // 4. draw widget
+            if ui.is_rect_visible(rect) {
+                let painter = ui.painter();
+                painter.rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(0));
+                let (from, to) = self.state.visible_beats(rect, beat_rate);
+                let bars = time_signatures.bars(from, to);
+                if bars.len() < (rect.width() / 4.0) as usize {
+                    for bar in bars {
+                        let x = self.state.beat_to_x(rect, bar, beat_rate);
+                        painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, eframe::epaint::Color32::from_gray(60)));
+                    }
+                }
+                let text_color = eframe::epaint::Color32::from_gray(200);
+                let signature = time_signatures.at_beat(from);
+                painter.text(
+                    rect.left_top(),
+                    egui::Align2::LEFT_TOP,
+                    format!("{}/{}", signature.numerator(), signature.denominator()),
+                    egui::FontId::proportional(10.0),
+                    text_color,
+                );
+                for (&pos, signature) in time_signatures.changes() {
+                    let x = self.state.beat_to_x(rect, pos, beat_rate);
+                    if rect.x_range().contains(&x) {
+                        painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, eframe::epaint::Color32::LIGHT_RED));
+                        painter.text(
+                            egui::pos2(x + 2.0, rect.center().y),
+                            egui::Align2::LEFT_CENTER,
+                            format!("{}/{}", signature.numerator(), signature.denominator()),
+                            egui::FontId::proportional(10.0),
+                            text_color,
+                        );
+                    }
+                }
+                self.state.draw_selection(ui, rect);
+                draw_playhead(ui, rect, self.state, self.song);
+            }
+        } else if ui.is_rect_visible(rect) {
             ui.painter()
                 .rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(0));
+            self.state.draw_selection(ui, rect);
+            draw_playhead(ui, rect, self.state, self.song);
+        }
+        res
+    }
+}
+
+impl<'a> egui::Widget for SwingWidget<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let (rect, res) = allocate_editor_space(ui);
+        // handle interactions
+        self.state.handle_view_input(ui, rect, &res, self.song);
+        if let (Some(swing), Some(beat_rate), Some(time_signatures)) = (self.swing, self.beat_rate, self.time_signatures) {
+            let changes = swing.changes().keys().copied().collect::<Vec<_>>();
+            // changes themselves sit on the straight grid
+            match self.state.marker_edit(ui, rect, &res, changes, beat_rate, time_signatures, None) {
+                Some(MarkerEdit::Add(pos)) => {
+                    // new sections swing the note value currently snapped to
+                    let ratio = swing.at_beat(pos).ratio();
+                    swing.add_change(pos, music::StaticSwing::new(ratio, self.state.snap));
+                }
+                Some(MarkerEdit::Remove(pos)) => {
+                    swing.remove_change(pos);
+                }
+                Some(MarkerEdit::Move { from, to }) if !swing.changes().contains_key(&to) => {
+                    if let Some(section) = swing.remove_change(from) {
+                        swing.add_change(to, section);
+                        self.state.dragged = Some(to);
+                    }
+                }
+                Some(MarkerEdit::Adjust { at, steps, fine }) => {
+                    let section = swing.at_beat(at);
+                    let step = if fine { 0.002 } else { 0.01 };
+                    swing.add_change(at, music::StaticSwing::new(section.ratio() + steps as f32 * step, section.unit()));
+                }
+                _ => (),
+            }
+            // draw widget
+            if ui.is_rect_visible(rect) {
+                let painter = ui.painter();
+                painter.rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(0));
+                let text_color = eframe::epaint::Color32::from_gray(200);
+                let describe = |section: music::StaticSwing| {
+                    format!("{:.0}% {}", section.ratio() * 100.0, section.unit())
+                };
+                let (from, _) = self.state.visible_beats(rect, beat_rate);
+                painter.text(
+                    rect.left_top(),
+                    egui::Align2::LEFT_TOP,
+                    format!("swing {}", describe(swing.at_beat(from))),
+                    egui::FontId::proportional(10.0),
+                    text_color,
+                );
+                for (&pos, &section) in swing.changes() {
+                    let x = self.state.beat_to_x(rect, pos, beat_rate);
+                    if rect.x_range().contains(&x) {
+                        painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, eframe::epaint::Color32::LIGHT_GREEN));
+                        painter.text(
+                            egui::pos2(x + 2.0, rect.center().y),
+                            egui::Align2::LEFT_CENTER,
+                            describe(section),
+                            egui::FontId::proportional(10.0),
+                            text_color,
+                        );
+                    }
+                }
+                self.state.draw_selection(ui, rect);
+                draw_playhead(ui, rect, self.state, self.song);
+            }
+        } else if ui.is_rect_visible(rect) {
+            ui.painter()
+                .rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(0));
+            self.state.draw_selection(ui, rect);
+            draw_playhead(ui, rect, self.state, self.song);
         }
         res
     }
@@ -402,10 +1461,104 @@ impl<'a> egui::Widget for LinesWidget<'a> {
         // 2. allocate space
         let (rect, res) = ui.allocate_exact_size(preferred_size, egui::Sense::click_and_drag());
         // 3. handle interactions
+        self.state.handle_view_input(ui, rect, &res, self.song);
+        if let (Some(beat_rate), Some(time_signatures)) = (self.beat_rate, self.time_signatures) {
+            let positions = self.lines.get_positions().iter().copied().collect::<Vec<_>>();
+            match self.state.marker_edit(ui, rect, &res, positions, beat_rate, time_signatures, self.swing) {
+                Some(MarkerEdit::Add(pos)) => {
+                    self.lines.insert(pos);
+                }
+                Some(MarkerEdit::Remove(pos)) => {
+                    self.lines.remove(pos);
+                }
+                Some(MarkerEdit::Move { from, to }) if !self.lines.get_positions().contains(&to) => {
+                    self.lines.remove(from);
+                    self.lines.insert(to);
+                    self.state.dragged = Some(to);
+                }
+                _ => (),
+            }
+        }
         // 4. draw widget
         if ui.is_rect_visible(rect) {
-            ui.painter()
-                .rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(0));
+            let painter = ui.painter();
+            painter.rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(0));
+            if let (Some(beat_rate), Some(time_signatures)) = (self.beat_rate, self.time_signatures) {
+                let (from, to) = self.state.visible_beats(rect, beat_rate);
+                let grid = match self.swing {
+                    Some(swing) => swing.grid(from, to, self.state.snap, time_signatures),
+                    None => time_signatures.grid(from, to, self.state.snap),
+                };
+                if grid.len() < (rect.width() / 4.0) as usize {
+                    for point in grid {
+                        let x = self.state.beat_to_x(rect, point, beat_rate);
+                        painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, eframe::epaint::Color32::from_gray(35)));
+                    }
+                }
+            }
+            if let Some(beat_rate) = self.beat_rate {
+                let stroke = egui::Stroke::new(1.0, eframe::epaint::Color32::from(self.color));
+                for &pos in self.lines.get_positions() {
+                    let x = self.state.beat_to_x(rect, pos, beat_rate);
+                    if rect.x_range().contains(&x) {
+                        painter.vline(x, rect.y_range(), stroke);
+                    }
+                }
+            }
+            self.state.draw_selection(ui, rect);
+            draw_playhead(ui, rect, self.state, self.song);
+        }
+        res
+    }
+}
+
+impl<'a> egui::Widget for OverviewWidget<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let preferred_size = egui::Vec2::new(ui.max_rect().size().x, 24.0);
+        let (rect, res) = ui.allocate_exact_size(preferred_size, egui::Sense::click_and_drag());
+        let song_secs = self.song.length().as_secs_f64().max(f64::EPSILON);
+        let time_to_x = |time: time::Duration| rect.left() + (time.as_secs_f64() / song_secs) as f32 * rect.width();
+
+        // clicking or dragging centers the editor rows on the pointer
+        if let Some(pos) = res.interact_pointer_pos().filter(|_| res.clicked() || res.dragged()) {
+            let time = (pos.x - rect.left()) as f64 / rect.width() as f64 * song_secs;
+            self.state.scroll_pos = time * self.state.pts_per_second - self.state.view_width / 2.0;
+            self.state.clamp_scroll(self.song.length());
+        }
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter();
+            painter.rect_filled(rect, 0.0, eframe::epaint::Color32::from_gray(20));
+
+            // line density, one bucket every couple of points
+            let mut buckets = vec![0usize; (rect.width() / 2.0).max(1.0) as usize];
+            let bucket_count = buckets.len();
+            for &time in &self.line_times {
+                let idx = (time.as_secs_f64() / song_secs * bucket_count as f64) as usize;
+                buckets[idx.min(bucket_count - 1)] += 1;
+            }
+            let densest = buckets.iter().copied().max().unwrap_or(0).max(1);
+            for (idx, &count) in buckets.iter().enumerate().filter(|(_, &count)| count > 0) {
+                let x = rect.left() + idx as f32 * 2.0;
+                let height = rect.height() * count as f32 / densest as f32;
+                painter.rect_filled(
+                    egui::Rect::from_min_max(egui::pos2(x, rect.bottom() - height), egui::pos2(x + 2.0, rect.bottom())),
+                    0.0,
+                    eframe::epaint::Color32::from_gray(140),
+                );
+            }
+
+            let view_start = self.state.scroll_pos / self.state.pts_per_second;
+            let view_end = view_start + self.state.view_width / self.state.pts_per_second;
+            let view = egui::Rect::from_x_y_ranges(
+                time_to_x(time::Duration::from_secs_f64(view_start))
+                    ..=time_to_x(time::Duration::from_secs_f64(view_end.min(song_secs))),
+                rect.y_range(),
+            );
+            painter.rect_stroke(view, 0.0, egui::Stroke::new(1.0, eframe::epaint::Color32::WHITE));
+
+            let playhead = time_to_x(self.song.position());
+            painter.vline(playhead, rect.y_range(), egui::Stroke::new(1.0, eframe::epaint::Color32::RED));
         }
         res
     }
@@ -420,6 +1573,15 @@ impl PipeDash {
             loaded_level_checksum: None,
             errors: VecDeque::new(),
             editor_mode: EditorMode::NoSong,
+            recovered_session: project::start_session(),
+            last_autosave: time::Instant::now(),
+            config: config::Config::load(),
+            settings_open: false,
+            calibration_open: false,
+            pending_load: None,
+            transfer: Default::default(),
+            copy_lines: Default::default(),
+            analysis: Default::default(),
         }
     }
 
@@ -430,13 +1592,31 @@ impl PipeDash {
                 ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
                     if ui
                         .add_enabled(
-                            self.selected_level.is_some(),
+                            self.selected_level.is_some() && self.pending_load.is_none(),
                             egui::Button::new("Load Level"),
                         )
                         .clicked()
                     {
                         self.msg_queue.push_back(Message::LoadLevel);
                     }
+                    if ui.button("Settings").clicked() {
+                        self.msg_queue.push_back(Message::ToggleSettings);
+                    }
+                    if ui.button("Import / Export").clicked() {
+                        self.msg_queue.push_back(Message::ToggleTransfer);
+                    }
+                    if ui.button("Check guidelines").clicked() {
+                        self.msg_queue.push_back(Message::ToggleAnalysis);
+                    }
+                    if ui
+                        .add_enabled(
+                            self.selected_level.is_some() && !matches!(self.editor_mode, EditorMode::NoSong),
+                            egui::Button::new("Copy guidelines from..."),
+                        )
+                        .clicked()
+                    {
+                        self.msg_queue.push_back(Message::ToggleCopyLines);
+                    }
                     egui::ScrollArea::vertical().show(ui, |ui| {
                         ui.with_layout(egui::Layout::top_down_justified(egui::Align::Min), |ui| {
                             for (idx, level) in self.level_list.iter().enumerate() {
@@ -456,10 +1636,247 @@ impl PipeDash {
             });
     }
 
+    fn settings_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.settings_open;
+        egui::Window::new("Settings").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Resources folder");
+                let mut path = self.config.resources_dir.display().to_string();
+                if ui.text_edit_singleline(&mut path).changed() {
+                    self.config.resources_dir = path.into();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Song info endpoint");
+                ui.text_edit_singleline(&mut self.config.server.song_info_url);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Secret");
+                ui.text_edit_singleline(&mut self.config.server.secret);
+            });
+            if ui.button("Reset server").clicked() {
+                self.config.server = Default::default();
+            }
+            ui.checkbox(&mut self.config.audio_output, "Play audio")
+                .on_hover_text("Takes effect when a level is loaded");
+            ui.horizontal(|ui| {
+                ui.label("Tap latency");
+                ui.add(egui::DragValue::new(&mut self.config.tap_latency).speed(0.001).max_decimals(3).suffix(" s"));
+                if ui.button("Calibrate").clicked() {
+                    self.msg_queue.push_back(Message::ToggleCalibration);
+                }
+            });
+            if ui.button("Save").clicked() {
+                self.msg_queue.push_back(Message::SaveSettings);
+            }
+        });
+        if open != self.settings_open {
+            self.msg_queue.push_back(Message::ToggleSettings);
+        }
+    }
+
+    fn calibration_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.calibration_open;
+        egui::Window::new("Tap latency").open(&mut open).show(ctx, |ui| {
+            let EditorMode::Full { editor, .. } = &mut self.editor_mode else {
+                ui.weak("Open a level with a tempo map first");
+                return;
+            };
+            ui.label("Play the song and tap T on every beat of the tempo map");
+            if editor.state.tap_mode != tap::TapMode::Calibrate {
+                if ui.button("Start").clicked() {
+                    editor.state.tap_mode = tap::TapMode::Calibrate;
+                    editor.state.taps.clear();
+                }
+                return;
+            }
+            let measured = editor.measured_latency();
+            match measured {
+                Some(latency) => ui.label(format!(
+                    "{} taps, {:.1} ms late",
+                    editor.state.taps.len(),
+                    latency * 1000.0
+                )),
+                None => ui.weak("No taps yet"),
+            };
+            ui.horizontal(|ui| {
+                if ui.add_enabled(measured.is_some(), egui::Button::new("Use")).clicked() {
+                    self.config.tap_latency = measured.unwrap_or_default();
+                    self.msg_queue.push_back(Message::SaveSettings);
+                }
+                if ui.button("Restart").clicked() {
+                    editor.state.taps.clear();
+                }
+                if ui.button("Stop").clicked() {
+                    editor.state.tap_mode = tap::TapMode::Off;
+                    editor.state.taps.clear();
+                }
+            });
+        });
+        if open != self.calibration_open {
+            self.msg_queue.push_back(Message::ToggleCalibration);
+        }
+    }
+
+    fn transfer_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.transfer.open;
+        let loaded = !matches!(self.editor_mode, EditorMode::NoSong);
+        let full = matches!(self.editor_mode, EditorMode::Full { .. }); // only the full editor has a tempo map
+        egui::Window::new("Import / Export").open(&mut open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("File");
+                ui.text_edit_singleline(&mut self.transfer.path);
+            });
+            ui.collapsing("Level file (.gmd)", |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Open level").clicked() {
+                        self.msg_queue.push_back(Message::ImportGmd);
+                    }
+                    if ui.add_enabled(loaded, egui::Button::new("Export with guidelines")).clicked() {
+                        self.msg_queue.push_back(Message::ExportGmd);
+                    }
+                });
+            });
+            if !loaded {
+                ui.weak("Load a level first");
+            }
+            ui.add_enabled_ui(loaded, |ui| {
+                ui.collapsing("MIDI", |ui| {
+                    midi_mapping_controls(ui, &mut self.transfer.midi_mappings);
+                    ui.horizontal(|ui| {
+                        if ui.button("Import MIDI").clicked() {
+                            self.msg_queue.push_back(Message::ImportMidi);
+                        }
+                        if ui.add_enabled(full, egui::Button::new("Export MIDI")).clicked() {
+                            self.msg_queue.push_back(Message::ExportMidi);
+                        }
+                    });
+                });
+                ui.collapsing("osu!", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Hit objects");
+                        egui::ComboBox::from_id_source("osu_hit_objects")
+                            .selected_text(match self.transfer.osu_hit_objects {
+                                Some(color) => format!("{color:?} lines"),
+                                None => "Ignore".to_string(),
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.transfer.osu_hit_objects, None, "Ignore");
+                                for color in [Color::Green, Color::Yellow, Color::Orange] {
+                                    ui.selectable_value(
+                                        &mut self.transfer.osu_hit_objects,
+                                        Some(color),
+                                        format!("{color:?} lines"),
+                                    );
+                                }
+                            });
+                    });
+                    if ui.button("Import osu! timing").clicked() {
+                        self.msg_queue.push_back(Message::ImportOsu);
+                    }
+                });
+                ui.collapsing("Audacity labels", |ui| {
+                    ui.label("One label per line, named g, y or o (or 1.0, 0.9, 0.8)");
+                    ui.horizontal(|ui| {
+                        if ui.button("Import labels").clicked() {
+                            self.msg_queue.push_back(Message::ImportLabels);
+                        }
+                        if ui.button("Export labels").clicked() {
+                            self.msg_queue.push_back(Message::ExportLabels);
+                        }
+                    });
+                });
+            });
+        });
+        if open != self.transfer.open {
+            self.msg_queue.push_back(Message::ToggleTransfer);
+        }
+    }
+
+    fn copy_lines_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.copy_lines.open;
+        let source = self.selected_level.and_then(|idx| self.level_list.get(idx));
+        egui::Window::new("Copy guidelines").open(&mut open).show(ctx, |ui| {
+            match source {
+                Some(level) => ui.label(level.display_name()),
+                None => ui.weak("Select a level to copy from"),
+            };
+            ui.horizontal(|ui| {
+                ui.label("Offset");
+                ui.add(egui::DragValue::new(&mut self.copy_lines.offset).speed(0.001).suffix(" s"));
+            });
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.copy_lines.replace, false, "Merge");
+                ui.radio_value(&mut self.copy_lines.replace, true, "Replace");
+            });
+            let loaded = !matches!(self.editor_mode, EditorMode::NoSong);
+            if ui.add_enabled(source.is_some() && loaded, egui::Button::new("Copy")).clicked() {
+                self.msg_queue.push_back(Message::CopyLines);
+            }
+        });
+        if open != self.copy_lines.open {
+            self.msg_queue.push_back(Message::ToggleCopyLines);
+        }
+    }
+
+    fn analysis_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.analysis.open;
+        egui::Window::new("Check guidelines").open(&mut open).show(ctx, |ui| {
+            let settings = &mut self.analysis.settings;
+            egui::Grid::new("analysis_settings").show(ui, |ui| {
+                for (label, value, range) in [
+                    ("Section length", &mut settings.section_length, 1.0..=600.0),
+                    ("Histogram step", &mut settings.histogram_bucket, 0.001..=1.0),
+                    ("Longest interval", &mut settings.histogram_max, 0.01..=60.0),
+                    ("Grid tolerance", &mut settings.grid_tolerance, 0.0..=0.5),
+                    ("Duplicate tolerance", &mut settings.duplicate_tolerance, 0.0..=0.5),
+                ] {
+                    ui.label(label);
+                    let mut secs = value.as_secs_f64();
+                    let drag = egui::DragValue::new(&mut secs).speed(0.001).clamp_range(range).suffix(" s");
+                    if ui.add(drag).changed() {
+                        *value = time::Duration::from_secs_f64(secs);
+                    }
+                    ui.end_row();
+                }
+            });
+            let loaded = !matches!(self.editor_mode, EditorMode::NoSong);
+            if ui.add_enabled(loaded, egui::Button::new("Check")).clicked() {
+                self.msg_queue.push_back(Message::Analyze);
+            }
+            if let Some(report) = &self.analysis.report {
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    ui.monospace(report.to_string());
+                });
+            }
+        });
+        if open != self.analysis.open {
+            self.msg_queue.push_back(Message::ToggleAnalysis);
+        }
+    }
+
     fn center_panel(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered_justified(|ui| {
                 use EditorMode::*;
+                if let Some(pending) = &self.pending_load {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Fetching song for \"{}\"", pending.level.name()));
+                        let bar = match pending.progress {
+                            (done, Some(total)) if total > 0 => {
+                                egui::ProgressBar::new(done as f32 / total as f32).show_percentage()
+                            }
+                            (done, _) => egui::ProgressBar::new(0.0)
+                                .text(format!("{} KiB", done / 1024))
+                                .animate(true),
+                        };
+                        ui.add(bar.desired_width(200.0));
+                        if ui.button("Cancel").clicked() {
+                            self.msg_queue.push_back(Message::CancelLoad);
+                        }
+                    });
+                }
                 ui.label(
                     egui::RichText::new(match &self.editor_mode {
                         RhythmWizard { song, .. } | Full { song, .. } => &song.name,
@@ -469,13 +1886,36 @@ impl PipeDash {
                 );
                 ui.label(
                     egui::RichText::new(match &self.editor_mode {
-                        RhythmWizard { song, .. } | Full { song, .. } => song.id.to_string(),
+                        RhythmWizard { song, .. } | Full { song, .. } => format!("{} ({})", song.artist, song.id),
                         NoSong => "No song loaded...".into(),
                     })
                     .size(20.0),
                 );
 
-                self.editor_mode.display(ui);
+                ui.horizontal(|ui| {
+                    if matches!(self.editor_mode, Full { .. }) && ui.button("Save project").clicked() {
+                        self.msg_queue.push_back(Message::SaveProject);
+                    }
+                    let newgrounds = matches!(
+                        self.loaded_level_checksum,
+                        Some((ref level, _)) if matches!(level.song(), gd::Song::Newgrounds { .. })
+                    );
+                    if newgrounds && ui.button("Refresh song info").clicked() {
+                        self.msg_queue.push_back(Message::RefreshSongInfo);
+                    }
+                });
+                if let Full { editor, .. } = &mut self.editor_mode {
+                    if custom_song_controls(ui, editor) {
+                        self.msg_queue.push_back(Message::ReloadSong);
+                    }
+                }
+                if let RhythmWizard { editor, .. } = &mut self.editor_mode {
+                    if quantize_controls(ui, editor) {
+                        self.msg_queue.push_back(Message::Quantize);
+                    }
+                }
+
+                self.editor_mode.display(ui, &self.config);
             });
         });
     }
@@ -485,44 +1925,344 @@ impl PipeDash {
             Message::LevelSelected(idx) => self.selected_level = Some(idx),
             Message::CloseError => { self.errors.pop_front(); },
             Message::LoadLevel => {
-                // Load song, GdlData, checksum; if there are no lines go straight into editor, otherwise
-                // start the rhythm wizard
                 let level = self
                     .selected_level
                     .and_then(|idx| self.level_list.get(idx))
                     .unwrap() // will not panic. selected_level range is same as level_list...
                     .clone(); // ...length - 1; message will not be sent if selected_level is none
-
-                let song = match Song::try_new(&level.song()) {
-                    Ok(song) => song,
+                self.load_level(level, None);
+            }
+            Message::SaveProject => {
+                let (Some((level, _)), EditorMode::Full { editor, .. }) = (&self.loaded_level_checksum, &self.editor_mode) else {
+                    return;
+                };
+                if let Err(e) = project::save(&level.into(), &editor.data) {
+                    log::error!("Couldn't save project for {}: {e}", level.name());
+                    self.errors.push_front(Box::new(e));
+                }
+            }
+            Message::RestoreSession => {
+                let Some(recovered) = self.recovered_session.take() else {
+                    return;
+                };
+                let found = self
+                    .level_list
+                    .iter()
+                    .position(|level| project::LevelKey::from(level) == recovered.level);
+                match found {
+                    Some(idx) => {
+                        self.selected_level = Some(idx);
+                        self.load_level(self.level_list[idx].clone(), Some(recovered.data));
+                    }
+                    None => self.errors.push_front(
+                        format!("Level \"{}\" is no longer in the save file", recovered.level.name).into(),
+                    ),
+                }
+            }
+            Message::DiscardRecovery => self.recovered_session = None,
+            Message::ToggleSettings => self.settings_open = !self.settings_open,
+            Message::ToggleTransfer => self.transfer.open = !self.transfer.open,
+            Message::ImportMidi => {
+                let imported = fs::read(&self.transfer.path)
+                    .map_err(Box::<dyn Error>::from)
+                    .and_then(|bytes| Ok(midi::import(&bytes, &self.transfer.midi_mappings)?));
+                match imported {
+                    Ok(data) => self.apply_import(data),
+                    Err(e) => {
+                        log::error!("Couldn't import {}: {e}", self.transfer.path);
+                        self.errors.push_front(e);
+                    }
+                }
+            }
+            Message::ExportMidi => {
+                let EditorMode::Full { editor, .. } = &self.editor_mode else {
+                    return;
+                };
+                let exported = midi::export(&editor.data)
+                    .map_err(Box::<dyn Error>::from)
+                    .and_then(|bytes| Ok(fs::write(&self.transfer.path, bytes)?));
+                if let Err(e) = exported {
+                    log::error!("Couldn't export {}: {e}", self.transfer.path);
+                    self.errors.push_front(e);
+                }
+            }
+            Message::ImportOsu => {
+                let imported = fs::read_to_string(&self.transfer.path)
+                    .map_err(Box::<dyn Error>::from)
+                    .and_then(|text| Ok(osu::import(&text, self.transfer.osu_hit_objects)?));
+                match imported {
+                    Ok(data) => self.apply_import(data),
+                    Err(e) => {
+                        log::error!("Couldn't import {}: {e}", self.transfer.path);
+                        self.errors.push_front(e);
+                    }
+                }
+            }
+            Message::ImportLabels => {
+                let imported = fs::read_to_string(&self.transfer.path)
+                    .map_err(Box::<dyn Error>::from)
+                    .and_then(|text| Ok(labels::import(&text)?));
+                match (imported, &mut self.editor_mode) {
+                    (Ok(lines), EditorMode::Full { editor, .. }) => editor.data.set_raw_lines(lines),
+                    (Ok(lines), EditorMode::RhythmWizard { editor, .. }) => editor.data.set_raw_lines(lines),
+                    (Ok(_), EditorMode::NoSong) => {}
+                    (Err(e), _) => {
+                        log::error!("Couldn't import {}: {e}", self.transfer.path);
+                        self.errors.push_front(e);
+                    }
+                }
+            }
+            Message::ExportLabels => {
+                let lines = match &self.editor_mode {
+                    EditorMode::Full { editor, .. } => editor.data.raw_lines(),
+                    EditorMode::RhythmWizard { editor, .. } => editor.data.raw_lines(),
+                    EditorMode::NoSong => return,
+                };
+                if let Err(e) = fs::write(&self.transfer.path, labels::export(&lines)) {
+                    log::error!("Couldn't export {}: {e}", self.transfer.path);
+                    self.errors.push_front(Box::new(e));
+                }
+            }
+            Message::ImportGmd => match gmd::import(self.transfer.path.as_ref()) {
+                Ok(level) => self.load_level(level, None),
+                Err(e) => {
+                    log::error!("Couldn't open {}: {e}", self.transfer.path);
+                    self.errors.push_front(Box::new(e));
+                }
+            },
+            Message::ExportGmd => {
+                let Some((level, _)) = &self.loaded_level_checksum else {
+                    return;
+                };
+                let lines = match &self.editor_mode {
+                    EditorMode::Full { editor, .. } => editor.data.raw_lines(),
+                    EditorMode::RhythmWizard { editor, .. } => editor.data.raw_lines(),
+                    EditorMode::NoSong => return,
+                };
+                let inner = level.load_inner().with_lines(&lines);
+                if let Err(e) = gmd::export(self.transfer.path.as_ref(), level, &inner) {
+                    log::error!("Couldn't export {}: {e}", self.transfer.path);
+                    self.errors.push_front(Box::new(e));
+                }
+            }
+            Message::ToggleCopyLines => self.copy_lines.open = !self.copy_lines.open,
+            Message::CopyLines => {
+                let Some(source) = self.selected_level.and_then(|idx| self.level_list.get(idx)) else {
+                    return;
+                };
+                let lines = match guidelines_of(source) {
+                    Ok(lines) => lines.shifted(self.copy_lines.offset),
                     Err(e) => {
-                        self.errors.push_front(Box::new(e)); 
+                        log::error!("Couldn't read the project of {}: {e}", source.name());
+                        self.errors.push_front(Box::new(e));
                         return;
-                    },
+                    }
                 };
-
-                let inner_level = level.load_inner();
-                let lines = inner_level.get_lines();
-                if lines.orange.empty() && lines.green.empty() && lines.yellow.empty() {
-                    self.editor_mode = EditorMode::Full {
-                        editor: Default::default(),
-                        song,
+                let replace = self.copy_lines.replace;
+                match &mut self.editor_mode {
+                    EditorMode::Full { editor, .. } if replace => editor.data.set_raw_lines(lines),
+                    EditorMode::Full { editor, .. } => editor.data.add_raw_lines(lines),
+                    EditorMode::RhythmWizard { editor, .. } if replace => editor.data.set_raw_lines(lines),
+                    EditorMode::RhythmWizard { editor, .. } => editor.data.add_raw_lines(lines),
+                    EditorMode::NoSong => {}
+                }
+            }
+            Message::ToggleCalibration => self.calibration_open = !self.calibration_open,
+            Message::ToggleAnalysis => self.analysis.open = !self.analysis.open,
+            Message::Analyze => {
+                let settings = self.analysis.settings;
+                self.analysis.report = match &self.editor_mode {
+                    EditorMode::Full { editor, song } => {
+                        let data = &editor.data;
+                        let grid = analysis::Grid {
+                            beat_rate: &data.beat_rate,
+                            time_signatures: &data.time_signatures,
+                            swing: &data.swing,
+                            subdivision: editor.state.snap,
+                        };
+                        Some(analysis::analyze(&data.raw_lines(), Some(grid), Some(song.length()), settings))
+                    }
+                    EditorMode::RhythmWizard { editor, song } => {
+                        let data = &editor.data;
+                        let grid = match (&data.beat_rate, &data.time_signatures, &data.swing) {
+                            (Some(beat_rate), Some(time_signatures), Some(swing)) => Some(analysis::Grid {
+                                beat_rate,
+                                time_signatures,
+                                swing,
+                                subdivision: editor.state.snap,
+                            }),
+                            _ => None,
+                        };
+                        Some(analysis::analyze(&data.raw_lines(), grid, Some(song.length()), settings))
+                    }
+                    EditorMode::NoSong => None,
+                };
+            }
+            Message::Quantize => {
+                // only the wizard has raw lines to quantize; anything else stays open as it is
+                if !matches!(self.editor_mode, EditorMode::RhythmWizard { .. }) {
+                    return;
+                }
+                let EditorMode::RhythmWizard { editor, song } = mem::replace(&mut self.editor_mode, EditorMode::NoSong) else {
+                    unreachable!("checked above");
+                };
+                let WizardEditor { state, data, quantize } = editor;
+                let (beat_rate, time_signatures) = match (data.beat_rate, data.time_signatures) {
+                    (Some(beat_rate), Some(time_signatures)) => (beat_rate, time_signatures),
+                    _ => music::steady_tempo(
+                        music::StaticBeatRate::from_bpm(quantize.bpm),
+                        music::StaticTimeSignature::new(quantize.signature.0, quantize.signature.1),
+                        quantize.offset,
+                    ),
+                };
+                let swing = data.swing.unwrap_or_default();
+                let tolerance = time::Duration::from_secs_f64(quantize.tolerance);
+                let mut unsnapped = Vec::new();
+                let mut quantized = |lines: &music::Lines<time::Duration>| {
+                    let result = lines.quantize(&beat_rate, &time_signatures, &swing, state.snap, tolerance, quantize.strength);
+                    unsnapped.extend(result.unsnapped);
+                    result.lines
+                };
+                let data = GdlData {
+                    green_lines: quantized(&data.green_lines),
+                    orange_lines: quantized(&data.orange_lines),
+                    yellow_lines: quantized(&data.yellow_lines),
+                    beat_rate,
+                    time_signatures,
+                    swing,
+                    custom_song: None,
+                };
+                self.editor_mode = EditorMode::Full { editor: Editor { state, data }, song };
+                if !unsnapped.is_empty() {
+                    unsnapped.sort();
+                    log::warn!("{} lines couldn't be quantized", unsnapped.len());
+                    self.errors.push_front(Box::new(UnsnappedLines(unsnapped)));
+                }
+            }
+            Message::DownloadProgress { done, total } => {
+                if let Some(pending) = &mut self.pending_load {
+                    pending.progress = (done, total);
+                }
+            }
+            Message::SongFetched(result) => {
+                let Some(PendingLoad { level, saved, .. }) = self.pending_load.take() else {
+                    return;
+                };
+                match result.and_then(|song_file| Song::try_new(song_file, self.config.audio_output)) {
+                    Ok(song) => self.finish_load(level, saved, song),
+                    Err(e) => {
+                        log::error!("Couldn't load song for {}: {e}", level.name());
+                        self.errors.push_front(Box::new(e));
                     }
-                } else {
-                    self.editor_mode = EditorMode::RhythmWizard {
-                        editor: lines.into(),
-                        song,
+                }
+            }
+            Message::CancelLoad => self.pending_load = None,
+            Message::ReloadSong => {
+                // the editor stays usable with the old song until the new one is ready
+                if let (Some((level, _)), EditorMode::Full { editor, .. }) = (&self.loaded_level_checksum, &self.editor_mode) {
+                    self.load_level(level.clone(), Some(editor.data.clone()));
+                }
+            }
+            Message::RefreshSongInfo => {
+                let (Some((level, _)), EditorMode::RhythmWizard { song, .. } | EditorMode::Full { song, .. }) = (&self.loaded_level_checksum, &mut self.editor_mode) else {
+                    return;
+                };
+                match song_cache::refresh(&level.song(), &self.config.server) {
+                    Ok(response) => {
+                        song.name = response.name().unwrap_or("Missing Name").into();
+                        song.artist = response.artist_name().unwrap_or_default().into();
+                    }
+                    Err(e) => {
+                        log::warn!("Couldn't refresh song info: {e}");
+                        self.errors.push_front(Box::new(e));
                     }
                 }
+            }
+            Message::SaveSettings => {
+                if let Err(e) = self.config.save() {
+                    log::error!("Couldn't save settings: {e}");
+                    self.errors.push_front(Box::new(e));
+                }
+            }
+        }
+    }
+
+    /// Starts fetching the level's song; the editor opens once it is decoded. `recovered` data
+    /// from a crashed session takes precedence over the saved project
+    fn load_level(&mut self, level: gd::Level, recovered: Option<GdlData>) {
+        let saved = recovered.or_else(|| {
+            project::load(&(&level).into()).unwrap_or_else(|e| {
+                log::warn!("Couldn't load project for {}: {e}", level.name());
+                self.errors.push_back(Box::new(e));
+                None
+            })
+        });
+        let custom_song = saved.as_ref().and_then(|data| data.custom_song.clone());
+        self.pending_load = Some(PendingLoad {
+            fetch: fetch::Fetch::start(level.song(), custom_song, &self.config),
+            level,
+            saved,
+            progress: (0, None),
+        });
+    }
+
+    /// Sets up GdlData and checksum once the song is in. With project data or no lines go
+    /// straight into editor, otherwise start the rhythm wizard
+    fn finish_load(&mut self, level: gd::Level, saved: Option<GdlData>, song: Song) {
+        let inner_level = level.load_inner();
+        let lines = inner_level.get_lines();
+        if let Some(data) = saved {
+            self.editor_mode = EditorMode::Full {
+                editor: Editor { state: Default::default(), data },
+                song,
+            }
+        } else if lines.orange.empty() && lines.green.empty() && lines.yellow.empty() {
+            self.editor_mode = EditorMode::Full {
+                editor: Default::default(),
+                song,
+            }
+        } else {
+            self.editor_mode = EditorMode::RhythmWizard {
+                editor: lines.into(),
+                song,
+            }
+        }
+
+        self.loaded_level_checksum = Some((level, inner_level.hash()));
+    }
 
-                self.loaded_level_checksum = Some((level, inner_level.hash()));
+    /// Replaces the editing data of the open level, switching to the full editor if needed
+    fn apply_import(&mut self, mut data: GdlData) {
+        self.editor_mode = match mem::replace(&mut self.editor_mode, EditorMode::NoSong) {
+            EditorMode::Full { editor, song } => {
+                data.custom_song = editor.data.custom_song;
+                EditorMode::Full { editor: Editor { state: editor.state, data }, song }
+            }
+            EditorMode::RhythmWizard { song, .. } => EditorMode::Full {
+                editor: Editor { state: Default::default(), data },
+                song,
+            },
+            EditorMode::NoSong => EditorMode::NoSong,
+        };
+    }
+
+    fn autosave(&mut self) {
+        self.last_autosave = time::Instant::now();
+        if let (Some((level, _)), EditorMode::Full { editor, .. }) = (&self.loaded_level_checksum, &self.editor_mode) {
+            if let Err(e) = project::autosave(&level.into(), &editor.data) {
+                log::warn!("Autosave failed: {e}");
             }
         }
     }
 
     fn handle_messages(&mut self) {
         for message in mem::take(&mut self.msg_queue) {
-            log::info!("{message:?}");
+            // progress arrives many times a second
+            if matches!(message, Message::DownloadProgress { .. }) {
+                log::trace!("{message:?}");
+            } else {
+                log::info!("{message:?}");
+            }
             self.handle_message(message);
         }
     }
@@ -532,6 +2272,20 @@ impl eframe::App for PipeDash {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         ctx.set_pixels_per_point(2.0);
 
+        if ctx.input_mut().consume_key(egui::Modifiers::COMMAND, egui::Key::S) {
+            self.msg_queue.push_back(Message::SaveProject);
+        }
+
+        if self.last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+            self.autosave();
+        }
+        ctx.request_repaint_after(AUTOSAVE_INTERVAL);
+
+        if let Some(pending) = &self.pending_load {
+            self.msg_queue.extend(pending.fetch.poll());
+            ctx.request_repaint_after(FETCH_POLL_INTERVAL);
+        }
+
         if let Some(boxed_err) = &self.errors.front() {
             egui::CentralPanel::default().show(ctx, |ui| {
                 ui.label(boxed_err.to_string());
@@ -539,13 +2293,37 @@ impl eframe::App for PipeDash {
                     self.msg_queue.push_back(Message::CloseError);
                 }
             });
+        } else if let Some(recovered) = &self.recovered_session {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.label(format!(
+                    "Pipedash didn't shut down properly last time. Restore the unsaved work on \"{}\"?",
+                    recovered.level.name
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").clicked() {
+                        self.msg_queue.push_back(Message::RestoreSession);
+                    }
+                    if ui.button("Discard").clicked() {
+                        self.msg_queue.push_back(Message::DiscardRecovery);
+                    }
+                });
+            });
         } else {
             self.side_panel(ctx, frame);
             self.center_panel(ctx, frame);
+            self.settings_window(ctx);
+            self.transfer_window(ctx);
+            self.copy_lines_window(ctx);
+            self.analysis_window(ctx);
+            self.calibration_window(ctx);
         }
 
         self.handle_messages();
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        project::end_session();
+    }
 }
 
 fn main() {
@@ -557,7 +2335,113 @@ fn main() {
             .expect("file creation failed?"),
     )
     .map_err(|e| println!("Logging uninitialized"));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
     let app: PipeDash;
     let opts = eframe::NativeOptions::default();
     eframe::run_native("PipeDash", opts, Box::new(|cc| Box::new(PipeDash::new(cc))));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> time::Duration {
+        time::Duration::from_secs(secs)
+    }
+
+    /// 10 points per second, 100 points wide
+    fn state() -> EditorState {
+        EditorState { view_width: 100.0, ..Default::default() }
+    }
+
+    #[test]
+    fn scrolling_stays_within_the_song() {
+        let mut state = state();
+        state.scroll_by(-5.0, secs(60));
+        assert_eq!(state.scroll_pos, 0.0);
+        state.scroll_by(1000.0, secs(60));
+        assert_eq!(state.scroll_pos, 500.0);
+        // a song shorter than the view doesn't scroll at all
+        state.scroll_by(50.0, secs(5));
+        assert_eq!(state.scroll_pos, 0.0);
+    }
+
+    #[test]
+    fn zoom_keeps_anchor_in_place() {
+        let mut state = state();
+        state.scroll_pos = 100.0;
+        state.zoom_around(2.0, 50.0, secs(60));
+        assert_eq!(state.pts_per_second, 20.0);
+        assert_eq!((state.scroll_pos + 50.0) / state.pts_per_second, 15.0);
+
+        state.zoom_around(1e6, 0.0, secs(60));
+        assert_eq!(state.pts_per_second, EditorState::MAX_ZOOM);
+        state.zoom_around(1e-9, 0.0, secs(60));
+        assert_eq!(state.pts_per_second, EditorState::MIN_ZOOM);
+        assert_eq!(state.scroll_pos, 0.0);
+    }
+
+    #[test]
+    fn zoom_to_fit_and_selection() {
+        let mut state = state();
+        state.zoom_to_fit(secs(50));
+        assert_eq!((state.pts_per_second, state.scroll_pos), (2.0, 0.0));
+
+        // nothing selected: nothing happens
+        state.zoom_to_selection(secs(50));
+        assert_eq!((state.pts_per_second, state.scroll_pos), (2.0, 0.0));
+        state.selection = Some((secs(10), secs(20)));
+        state.zoom_to_selection(secs(50));
+        assert_eq!((state.pts_per_second, state.scroll_pos), (10.0, 100.0));
+
+        // before the first frame there's no view to fit into
+        let mut unsized = EditorState::default();
+        unsized.zoom_to_fit(secs(50));
+        assert_eq!(unsized.pts_per_second, 10.0);
+    }
+
+    fn times(lines: &music::Lines<time::Duration>) -> Vec<f64> {
+        lines.get_positions().iter().map(time::Duration::as_secs_f64).collect()
+    }
+
+    fn beats(lines: &music::Lines) -> Vec<f32> {
+        lines.get_positions().iter().map(|pos| pos.0).collect()
+    }
+
+    #[test]
+    fn project_guidelines_win_over_saved_ones() {
+        // 120 bpm, so beat 2 is at 1s
+        let mut data = GdlData::default();
+        data.green_lines.insert(2.0.into());
+        let lines = preferred_guidelines(Some(&data), || panic!("the level's own lines aren't needed"));
+        assert_eq!(times(&lines.green), [1.0]);
+
+        let lines = preferred_guidelines(None, || {
+            let mut saved = gd::RawLinesTriplet::default();
+            saved.orange.insert(secs(4));
+            saved
+        });
+        assert_eq!(times(&lines.orange), [4.0]);
+        assert!(lines.green.empty());
+    }
+
+    #[test]
+    fn copied_lines_merge_or_replace() {
+        let mut copied = gd::RawLinesTriplet::default();
+        copied.green.insert(time::Duration::from_millis(1500));
+        copied.yellow.insert(secs(2));
+
+        let mut data = GdlData::default();
+        data.green_lines.insert(1.0.into());
+        data.add_raw_lines(copied.shifted(0.5));
+        assert_eq!(beats(&data.green_lines), [1.0, 4.0]);
+        assert_eq!(beats(&data.yellow_lines), [5.0]);
+
+        data.set_raw_lines(copied.shifted(-1.0));
+        assert_eq!(beats(&data.green_lines), [1.0]);
+        assert_eq!(beats(&data.yellow_lines), [2.0]);
+    }
+}
@@ -0,0 +1,404 @@
+use crate::music::{BeatPosition, BeatRate, StaticBeatRate, StaticTimeSignature, TimeSignature};
+use crate::{Color, GdlData};
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use ordered_float::OrderedFloat as Float;
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Microseconds per quarter note when a file doesn't set a tempo (120 bpm)
+const DEFAULT_TEMPO: u32 = 500_000;
+const DEFAULT_SIGNATURE: StaticTimeSignature = StaticTimeSignature::new(4, 4);
+const EXPORT_TICKS_PER_QUARTER: u16 = 480;
+/// Key and name of the track each colour is exported to; `NoteMapping::exported` reads them back
+const EXPORT_TRACKS: [(Color, u8, &str); 3] = [
+    (Color::Green, 60, "Green lines"),
+    (Color::Yellow, 62, "Yellow lines"),
+    (Color::Orange, 64, "Orange lines"),
+];
+
+#[derive(Debug, Error)]
+pub enum MidiError {
+    #[error("Not a valid MIDI file")]
+    Parse(#[from] midly::Error),
+    #[error("MIDI files timed in SMPTE frames aren't supported")]
+    Timecode,
+    #[error("Couldn't write MIDI file")]
+    Io(#[from] io::Error),
+    #[error("Invalid time signature ({numerator} over 2 to the power of {denominator_pow})")]
+    InvalidTimeSignature { numerator: u8, denominator_pow: u8 },
+}
+
+/// Which notes become lines of which colour; `None` matches any track or key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoteMapping {
+    pub track: Option<usize>,
+    pub key: Option<u8>,
+    pub color: Color,
+}
+
+/// MIDI counts in quarter notes, but a beat here is one of the time signature's denominator,
+/// so the two drift apart whenever the denominator isn't 4
+struct BeatScale {
+    /// (quarters, beats, denominator) at every signature change, starting at 0
+    segments: Vec<(f64, f64, u32)>,
+}
+
+impl NoteMapping {
+    /// Reads back the lines of a file made by `export`
+    pub fn exported() -> Vec<Self> {
+        EXPORT_TRACKS
+            .iter()
+            .map(|&(color, key, _)| Self { track: None, key: Some(key), color })
+            .collect()
+    }
+
+    fn matches(&self, track: usize, key: u8) -> bool {
+        self.track.map_or(true, |t| t == track) && self.key.map_or(true, |k| k == key)
+    }
+}
+
+impl BeatScale {
+    /// `denominators` as (quarters, denominator), in order
+    fn from_quarters(denominators: impl IntoIterator<Item = (f64, u32)>) -> Self {
+        let mut segments = vec![(0.0, 0.0, DEFAULT_SIGNATURE.denominator())];
+        for (quarters, denominator) in denominators {
+            let beats = Self { segments: segments.clone() }.beats(quarters);
+            segments.retain(|&(q, _, _)| q < quarters);
+            segments.push((quarters, beats, denominator));
+        }
+        Self { segments }
+    }
+
+    /// `denominators` as (beats, denominator), in order
+    fn from_beats(denominators: impl IntoIterator<Item = (f64, u32)>) -> Self {
+        let mut segments = vec![(0.0, 0.0, DEFAULT_SIGNATURE.denominator())];
+        for (beats, denominator) in denominators {
+            let quarters = Self { segments: segments.clone() }.quarters(beats);
+            segments.retain(|&(_, b, _)| b < beats);
+            segments.push((quarters, beats, denominator));
+        }
+        Self { segments }
+    }
+
+    fn segment_at_quarters(&self, quarters: f64) -> (f64, f64, u32) {
+        *self
+            .segments
+            .iter()
+            .rev()
+            .find(|&&(q, _, _)| q <= quarters)
+            .unwrap_or(&self.segments[0])
+    }
+
+    fn beats(&self, quarters: f64) -> f64 {
+        let (start_quarters, start_beats, denominator) = self.segment_at_quarters(quarters);
+        start_beats + (quarters - start_quarters) * denominator as f64 / 4.0
+    }
+
+    fn denominator_at_quarters(&self, quarters: f64) -> u32 {
+        self.segment_at_quarters(quarters).2
+    }
+
+    fn quarters(&self, beats: f64) -> f64 {
+        let (start_quarters, start_beats, denominator) = *self
+            .segments
+            .iter()
+            .rev()
+            .find(|&&(_, b, _)| b <= beats)
+            .unwrap_or(&self.segments[0]);
+        start_quarters + (beats - start_beats) * 4.0 / denominator as f64
+    }
+}
+
+/// Length of one beat, given a MIDI tempo (per quarter note) and the signature's denominator
+fn beat_rate(tempo: u32, denominator: u32) -> StaticBeatRate {
+    StaticBeatRate::from_beat_length(Duration::from_micros(tempo as u64 * 4 / denominator as u64))
+}
+
+/// Reads a Standard MIDI File: tempo and time signature events become the tempo map, and note-ons
+/// matching `mappings` become lines
+pub fn import(bytes: &[u8], mappings: &[NoteMapping]) -> Result<GdlData, MidiError> {
+    let smf = Smf::parse(bytes)?;
+    let Timing::Metrical(ticks_per_quarter) = smf.header.timing else {
+        return Err(MidiError::Timecode);
+    };
+    let ticks_per_quarter = ticks_per_quarter.as_int() as f64;
+
+    let mut tempos = BTreeMap::new();
+    let mut signatures = BTreeMap::new();
+    let mut notes = Vec::new();
+    for (track_idx, track) in smf.tracks.iter().enumerate() {
+        let mut tick = 0u64;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                    tempos.insert(tick, tempo.as_int());
+                }
+                TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, denominator_pow, _, _)) => {
+                    // an empty bar would never end, and the denominator has to fit
+                    let denominator = 1u32
+                        .checked_shl(denominator_pow as u32)
+                        .filter(|_| numerator > 0)
+                        .ok_or(MidiError::InvalidTimeSignature { numerator, denominator_pow })?;
+                    signatures.insert(tick, StaticTimeSignature::new(numerator as u32, denominator));
+                }
+                // a note-on without velocity is a note-off
+                TrackEventKind::Midi { message: MidiMessage::NoteOn { key, vel }, .. } if vel > 0 => {
+                    notes.extend(
+                        mappings
+                            .iter()
+                            .filter(|mapping| mapping.matches(track_idx, key.as_int()))
+                            .map(|mapping| (tick, mapping.color)),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let quarters = |tick: u64| tick as f64 / ticks_per_quarter;
+    let scale = BeatScale::from_quarters(
+        signatures
+            .iter()
+            .map(|(&tick, signature)| (quarters(tick), signature.denominator())),
+    );
+    let beat = |tick: u64| Float(scale.beats(quarters(tick)) as f32);
+
+    let mut time_signatures: TimeSignature = signatures.get(&0).copied().unwrap_or(DEFAULT_SIGNATURE).into();
+    for (&tick, &signature) in signatures.range(1..) {
+        time_signatures.add_change(beat(tick), signature);
+    }
+
+    // the beat length changes with the tempo, but also with the signature's denominator
+    let tempo_at = |tick: u64| tempos.range(..=tick).next_back().map_or(DEFAULT_TEMPO, |(_, &tempo)| tempo);
+    let rate_at = |tick: u64| beat_rate(tempo_at(tick), scale.denominator_at_quarters(quarters(tick)));
+    let mut rate_ticks: Vec<u64> = tempos.keys().chain(signatures.keys()).copied().filter(|&t| t > 0).collect();
+    rate_ticks.sort_unstable();
+    rate_ticks.dedup();
+    let mut beat_rate: BeatRate = rate_at(0).into();
+    let mut previous = rate_at(0);
+    for tick in rate_ticks {
+        let rate = rate_at(tick);
+        if rate != previous {
+            beat_rate.add_change(beat(tick), rate);
+            previous = rate;
+        }
+    }
+
+    let mut data = GdlData {
+        beat_rate,
+        time_signatures,
+        ..Default::default()
+    };
+    for (tick, color) in notes {
+        data.lines_mut(color).insert(beat(tick));
+    }
+    Ok(data)
+}
+
+/// Writes the tempo map and lines as a Standard MIDI File: a conductor track, then one track per
+/// colour with a short note on each line
+pub fn export(data: &GdlData) -> Result<Vec<u8>, MidiError> {
+    let signatures = &data.time_signatures;
+    let scale = BeatScale::from_beats(
+        std::iter::once((0.0, signatures.at_beat(Float(0.0)).denominator())).chain(
+            signatures
+                .changes()
+                .iter()
+                .map(|(&pos, signature)| (*pos as f64, signature.denominator())),
+        ),
+    );
+    let tick = |pos: BeatPosition| (scale.quarters(*pos as f64) * EXPORT_TICKS_PER_QUARTER as f64).round() as u64;
+
+    let mut conductor = Vec::new();
+    for (pos, signature) in std::iter::once((Float(0.0), signatures.at_beat(Float(0.0))))
+        .chain(signatures.changes().iter().map(|(&pos, &signature)| (pos, signature)))
+    {
+        // MIDI can only store power-of-two denominators
+        let denominator_pow = signature.denominator().next_power_of_two().trailing_zeros() as u8;
+        let meta = MetaMessage::TimeSignature(signature.numerator() as u8, denominator_pow, 24, 8);
+        conductor.push((tick(pos), TrackEventKind::Meta(meta)));
+    }
+    // tempos are per quarter note, so a new denominator needs a new tempo too
+    let mut tempo_points: Vec<BeatPosition> = std::iter::once(Float(0.0))
+        .chain(data.beat_rate.changes().keys().copied())
+        .chain(signatures.changes().keys().copied())
+        .collect();
+    tempo_points.sort_unstable();
+    tempo_points.dedup();
+    let mut previous = None;
+    for pos in tempo_points {
+        let beat_length = data.beat_rate.at_beat(pos).beat_length().as_micros();
+        let denominator = signatures.at_beat(pos).denominator() as u128;
+        let tempo = (beat_length * denominator / 4).min(u24::max_value().as_int() as u128) as u32;
+        if previous != Some(tempo) {
+            conductor.push((tick(pos), TrackEventKind::Meta(MetaMessage::Tempo(u24::new(tempo)))));
+            previous = Some(tempo);
+        }
+    }
+
+    let mut tracks = vec![track(conductor)];
+    let note_length = EXPORT_TICKS_PER_QUARTER as u64 / 4;
+    for (color, key, name) in EXPORT_TRACKS {
+        let mut events = vec![(0, TrackEventKind::Meta(MetaMessage::TrackName(name.as_bytes())))];
+        for &pos in data.lines(color).get_positions() {
+            let on = tick(pos);
+            let channel = u4::new(0);
+            let key = u7::new(key);
+            events.push((on, TrackEventKind::Midi { channel, message: MidiMessage::NoteOn { key, vel: u7::new(100) } }));
+            events.push((on + note_length, TrackEventKind::Midi { channel, message: MidiMessage::NoteOff { key, vel: u7::new(0) } }));
+        }
+        tracks.push(track(events));
+    }
+
+    let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(u15::new(EXPORT_TICKS_PER_QUARTER))));
+    smf.tracks = tracks;
+    let mut bytes = Vec::new();
+    smf.write_std(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Turns absolutely timed events into a track; note-offs go before note-ons at the same tick
+fn track(mut events: Vec<(u64, TrackEventKind)>) -> Vec<TrackEvent> {
+    let is_note_on = |kind: &TrackEventKind| matches!(kind, TrackEventKind::Midi { message: MidiMessage::NoteOn { .. }, .. });
+    events.sort_by_key(|(tick, kind)| (*tick, is_note_on(kind)));
+    let mut last_tick = 0;
+    let mut track: Vec<TrackEvent> = events
+        .into_iter()
+        .map(|(tick, kind)| {
+            let delta = u28::new((tick - last_tick) as u32);
+            last_tick = tick;
+            TrackEvent { delta, kind }
+        })
+        .collect();
+    track.push(TrackEvent { delta: u28::new(0), kind: TrackEventKind::Meta(MetaMessage::EndOfTrack) });
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::Track;
+
+    fn event(delta: u32, kind: TrackEventKind<'static>) -> TrackEvent<'static> {
+        TrackEvent { delta: u28::new(delta), kind }
+    }
+
+    fn note_on(delta: u32, key: u8) -> TrackEvent<'static> {
+        event(
+            delta,
+            TrackEventKind::Midi {
+                channel: u4::new(0),
+                message: MidiMessage::NoteOn { key: u7::new(key), vel: u7::new(100) },
+            },
+        )
+    }
+
+    fn encode(tracks: Vec<Track<'static>>) -> Vec<u8> {
+        let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(u15::new(480))));
+        smf.tracks = tracks;
+        let mut bytes = Vec::new();
+        smf.write_std(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn imports_tempo_map_and_notes() {
+        let conductor = vec![
+            event(0, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(500_000)))),
+            event(0, TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, 24, 8))),
+            // after one 4/4 bar: 6/8 at 150 bpm
+            event(1920, TrackEventKind::Meta(MetaMessage::TimeSignature(6, 3, 24, 8))),
+            event(0, TrackEventKind::Meta(MetaMessage::Tempo(u24::new(400_000)))),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ];
+        let drums = vec![note_on(0, 36), note_on(480, 38), note_on(1440, 36), note_on(240, 36)];
+        let mappings = [
+            NoteMapping { track: Some(1), key: Some(36), color: Color::Green },
+            NoteMapping { track: None, key: Some(38), color: Color::Orange },
+        ];
+        let data = import(&encode(vec![conductor, drums]), &mappings).unwrap();
+
+        assert_eq!(data.time_signatures.at_beat(Float(0.0)), StaticTimeSignature::new(4, 4));
+        assert_eq!(data.time_signatures.at_beat(Float(4.0)), StaticTimeSignature::new(6, 8));
+        assert_eq!(data.beat_rate.at_beat(Float(0.0)).beat_length(), Duration::from_millis(500));
+        // an eighth at 150 bpm
+        assert_eq!(data.beat_rate.at_beat(Float(4.0)).beat_length(), Duration::from_millis(200));
+
+        let green: Vec<f32> = data.green_lines.get_positions().iter().map(|p| p.0).collect();
+        let orange: Vec<f32> = data.orange_lines.get_positions().iter().map(|p| p.0).collect();
+        // the last kick is an eighth into the 6/8 bar, which is one beat there
+        assert_eq!(green, [0.0, 4.0, 5.0]);
+        assert_eq!(orange, [1.0]);
+        assert!(data.yellow_lines.empty());
+    }
+
+    #[test]
+    fn defaults_to_120_bpm_in_four_four() {
+        let data = import(&encode(vec![vec![note_on(960, 60)]]), &[
+            NoteMapping { track: None, key: None, color: Color::Yellow },
+        ])
+        .unwrap();
+        assert_eq!(data.beat_rate.at_beat(Float(0.0)).beat_length(), Duration::from_millis(500));
+        assert_eq!(data.time_signatures.at_beat(Float(0.0)), DEFAULT_SIGNATURE);
+        let yellow: Vec<f32> = data.yellow_lines.get_positions().iter().map(|p| p.0).collect();
+        assert_eq!(yellow, [2.0]);
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let mut data = GdlData::default();
+        data.time_signatures.add_change(Float(4.0), StaticTimeSignature::new(6, 8));
+        data.time_signatures.add_change(Float(10.0), StaticTimeSignature::new(3, 4));
+        data.beat_rate = StaticBeatRate::from_bpm(100.0).into();
+        // eighths at 150 bpm in the 6/8 section
+        data.beat_rate.add_change(Float(4.0), StaticBeatRate::from_beat_length(Duration::from_millis(200)));
+        for pos in [0.0, 1.5, 4.0, 7.0, 10.0, 12.5] {
+            data.green_lines.insert(Float(pos));
+        }
+        data.orange_lines.insert(Float(4.0));
+        data.yellow_lines.insert(Float(11.0));
+
+        let imported = import(&export(&data).unwrap(), &NoteMapping::exported()).unwrap();
+        assert_eq!(imported.time_signatures.changes(), data.time_signatures.changes());
+        assert_eq!(imported.time_signatures.at_beat(Float(0.0)), StaticTimeSignature::new(4, 4));
+        for pos in [0.0, 4.0, 10.0, 11.0] {
+            let original = data.beat_rate.at_beat(Float(pos)).beat_length().as_micros();
+            let read_back = imported.beat_rate.at_beat(Float(pos)).beat_length().as_micros();
+            assert!(original.abs_diff(read_back) <= 1, "{original} != {read_back} at {pos}");
+        }
+        assert_eq!(imported.green_lines.get_positions(), data.green_lines.get_positions());
+        assert_eq!(imported.orange_lines.get_positions(), data.orange_lines.get_positions());
+        assert_eq!(imported.yellow_lines.get_positions(), data.yellow_lines.get_positions());
+    }
+
+    #[test]
+    fn rejects_timecode_files() {
+        let mut smf = Smf::new(Header::new(Format::SingleTrack, Timing::Timecode(midly::Fps::Fps25, 40)));
+        smf.tracks.push(vec![]);
+        let mut bytes = Vec::new();
+        smf.write_std(&mut bytes).unwrap();
+        assert!(matches!(import(&bytes, &[]), Err(MidiError::Timecode)));
+    }
+
+    #[test]
+    fn rejects_empty_bars() {
+        let track = vec![event(0, TrackEventKind::Meta(MetaMessage::TimeSignature(0, 2, 24, 8)))];
+        assert!(matches!(
+            import(&encode(vec![track]), &[]),
+            Err(MidiError::InvalidTimeSignature { numerator: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_huge_denominators() {
+        let track = vec![event(0, TrackEventKind::Meta(MetaMessage::TimeSignature(4, 32, 24, 8)))];
+        assert!(matches!(
+            import(&encode(vec![track]), &[]),
+            Err(MidiError::InvalidTimeSignature { denominator_pow: 32, .. })
+        ));
+    }
+}
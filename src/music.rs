@@ -1,32 +1,82 @@
 use std::time::Duration;
 use ordered_float::OrderedFloat as Float;
 use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound::{Excluded, Unbounded};
 use rodio::{Source, Sample};
+use serde::{Deserialize, Serialize};
 
 pub type BeatPosition = Float<f32>;
 
 /// Like BPM, but not necessarily represented in terms of minutes
 /// Only BPM jumps for now; no smooth accel/decel
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BeatRate {
     initial: StaticBeatRate,
     changes: BTreeMap<BeatPosition, StaticBeatRate>,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub struct StaticBeatRate(Duration);
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TimeSignature {
     initial: StaticTimeSignature,
     changes: BTreeMap<BeatPosition, StaticTimeSignature>,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct StaticTimeSignature {
     numerator: u32,
     denominator: u32,
 }
 
-#[derive(Debug)]
+/// Snapping grid spacing: a note value (1/`note`), optionally dotted or as part of a tuplet.
+/// Note values are relative to the time signature's denominator, so a 1/4 in 6/8 is two beats.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Subdivision {
+    pub note: u32,
+    pub kind: SubdivisionKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SubdivisionKind {
+    Straight,
+    Dotted,
+    /// `notes` evenly spaced in the time of `in_space_of` straight ones
+    Tuplet { notes: u32, in_space_of: u32 },
+}
+
+/// Swing per section; changes behave like `BeatRate` changes
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Swing {
+    initial: StaticSwing,
+    changes: BTreeMap<BeatPosition, StaticSwing>,
+}
+
+/// Delays every second `unit` note so that each pair is split `ratio` : 1 - `ratio`.
+/// A ratio of 0.5 is straight, 2/3 is triplet swing
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StaticSwing {
+    ratio: f32,
+    unit: Subdivision,
+}
+
+/// A rhythm repeated every bar: which steps of `step` get a line
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pattern {
+    pub step: Subdivision,
+    pub hits: Vec<bool>,
+}
+
+/// Raw lines moved onto a grid by `Lines::quantize`
+#[derive(Clone, Debug)]
+pub struct Quantized {
+    pub lines: Lines,
+    /// Lines too far from the grid to snap; they keep their time
+    pub unsnapped: Vec<Duration>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Lines<T = BeatPosition>
 where
     T: Ord,
@@ -38,6 +88,18 @@ impl StaticBeatRate {
     pub fn from_bpm(bpm: f32) -> Self {
         Self(Duration::from_secs_f32(60.0 / bpm))
     }
+
+    pub fn from_beat_length(length: Duration) -> Self {
+        Self(length)
+    }
+
+    pub fn bpm(self) -> f32 {
+        60.0 / self.0.as_secs_f32()
+    }
+
+    pub fn beat_length(self) -> Duration {
+        self.0
+    }
 }
 
 impl From<StaticBeatRate> for BeatRate {
@@ -72,6 +134,49 @@ impl BeatRate {
     pub fn add_change(&mut self, new_pos: BeatPosition, new_rate: StaticBeatRate) {
         self.changes.insert(new_pos, new_rate);
     }
+
+    pub fn remove_change(&mut self, pos: BeatPosition) -> Option<StaticBeatRate> {
+        self.changes.remove(&pos)
+    }
+
+    pub fn changes(&self) -> &BTreeMap<BeatPosition, StaticBeatRate> {
+        &self.changes
+    }
+
+    /// Stretches of constant rate as (first beat, rate), in order
+    fn sections(&self) -> impl Iterator<Item = (BeatPosition, StaticBeatRate)> + '_ {
+        std::iter::once((Float(0.0), self.initial))
+            .chain(self.changes.iter().map(|(&pos, &rate)| (pos, rate)))
+    }
+
+    /// Time from beat 0 to `pos`
+    pub fn time_at(&self, pos: BeatPosition) -> Duration {
+        let mut time = Duration::ZERO;
+        let mut sections = self.sections().peekable();
+        while let Some((start, rate)) = sections.next() {
+            let end = sections.peek().map_or(pos, |&(next, _)| next.min(pos));
+            if end <= start {
+                break;
+            }
+            time += rate.0.mul_f32(*(end - start));
+        }
+        time
+    }
+
+    /// Beat that falls at `time`; inverse of `time_at`
+    pub fn beat_at(&self, time: Duration) -> BeatPosition {
+        let mut elapsed = Duration::ZERO;
+        let mut sections = self.sections().peekable();
+        while let Some((start, rate)) = sections.next() {
+            match sections.peek() {
+                Some(&(next, _)) if elapsed + rate.0.mul_f32(*(next - start)) <= time => {
+                    elapsed += rate.0.mul_f32(*(next - start));
+                }
+                _ => return start + (time - elapsed).div_duration_f32(rate.0),
+            }
+        }
+        unreachable!("there is always at least the initial section")
+    }
 }
 
 /// Changes: when the time signature changes, the bar immediately resets
@@ -83,11 +188,76 @@ impl StaticTimeSignature {
         }
     }
 
+    pub fn numerator(self) -> u32 {
+        self.numerator
+    }
+
+    pub fn denominator(self) -> u32 {
+        self.denominator
+    }
+
     fn beats_per_bar(self) -> BeatPosition {
         (self.numerator as f32).into()
     }
 }
 
+impl Subdivision {
+    pub const fn straight(note: u32) -> Self {
+        Self { note, kind: SubdivisionKind::Straight }
+    }
+
+    pub const fn triplet(note: u32) -> Self {
+        Self { note, kind: SubdivisionKind::Tuplet { notes: 3, in_space_of: 2 } }
+    }
+
+    pub const fn quintuplet(note: u32) -> Self {
+        Self { note, kind: SubdivisionKind::Tuplet { notes: 5, in_space_of: 4 } }
+    }
+
+    pub const fn dotted(note: u32) -> Self {
+        Self { note, kind: SubdivisionKind::Dotted }
+    }
+
+    /// Length of one grid step in beats of `signature`
+    pub fn beats(self, signature: StaticTimeSignature) -> f32 {
+        let straight = signature.denominator as f32 / self.note.max(1) as f32;
+        match self.kind {
+            SubdivisionKind::Straight => straight,
+            SubdivisionKind::Dotted => straight * 1.5,
+            SubdivisionKind::Tuplet { notes, in_space_of } => {
+                straight * in_space_of as f32 / notes.max(1) as f32
+            }
+        }
+    }
+}
+
+impl Default for Subdivision {
+    fn default() -> Self {
+        Self::straight(4)
+    }
+}
+
+impl std::fmt::Display for SubdivisionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Straight => write!(f, "straight"),
+            Self::Dotted => write!(f, "dotted"),
+            Self::Tuplet { notes: 3, in_space_of: 2 } => write!(f, "triplet"),
+            Self::Tuplet { notes: 5, in_space_of: 4 } => write!(f, "quintuplet"),
+            Self::Tuplet { notes, in_space_of } => write!(f, "{notes}:{in_space_of}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Subdivision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            SubdivisionKind::Straight => write!(f, "1/{}", self.note),
+            kind => write!(f, "1/{} {kind}", self.note),
+        }
+    }
+}
+
 impl From<StaticTimeSignature> for TimeSignature {
     fn from(rhs: StaticTimeSignature) -> Self {
         Self {
@@ -102,6 +272,74 @@ impl TimeSignature {
         self.changes.insert(position, signature);
     }
 
+    pub fn remove_change(&mut self, position: BeatPosition) -> Option<StaticTimeSignature> {
+        self.changes.remove(&position)
+    }
+
+    pub fn changes(&self) -> &BTreeMap<BeatPosition, StaticTimeSignature> {
+        &self.changes
+    }
+
+    /// False if any signature has empty bars or beats, which would stall `bars` and `grid`
+    pub fn is_valid(&self) -> bool {
+        std::iter::once(&self.initial)
+            .chain(self.changes.values())
+            .all(|signature| signature.numerator > 0 && signature.denominator > 0)
+    }
+
+    /// Start of the bar after the one starting at `bar_start`; cut short by signature changes
+    fn next_bar(&self, bar_start: BeatPosition) -> BeatPosition {
+        let full_bar = bar_start + self.at_beat(bar_start).beats_per_bar();
+        match self.changes.range((Excluded(bar_start), Unbounded)).next() {
+            Some((&change, _)) if change < full_bar => change,
+            _ => full_bar,
+        }
+    }
+
+    /// Nearest grid point to `pos`; the grid restarts at every bar line
+    pub fn snap(&self, pos: BeatPosition, subdivision: Subdivision) -> BeatPosition {
+        let bar_start = pos - self.position_in_bar(pos);
+        let step = subdivision.beats(self.at_beat(bar_start));
+        let snapped = bar_start + (*(pos - bar_start) / step).round() * step;
+        let next_bar = self.next_bar(bar_start);
+        if *(next_bar - pos) < (snapped - pos).abs() {
+            next_bar
+        } else {
+            snapped
+        }
+    }
+
+    /// Bar lines in `from..to`
+    pub fn bars(&self, from: BeatPosition, to: BeatPosition) -> Vec<BeatPosition> {
+        let mut bar = from - self.position_in_bar(from);
+        let mut bars = Vec::new();
+        while bar < to {
+            if bar >= from {
+                bars.push(bar);
+            }
+            bar = self.next_bar(bar);
+        }
+        bars
+    }
+
+    /// Grid points in `from..to`
+    pub fn grid(&self, from: BeatPosition, to: BeatPosition, subdivision: Subdivision) -> Vec<BeatPosition> {
+        let mut points = Vec::new();
+        let mut bar = from - self.position_in_bar(from);
+        while bar < to {
+            let next_bar = self.next_bar(bar);
+            let step = subdivision.beats(self.at_beat(bar));
+            points.extend(
+                (0..)
+                    .map(|i| bar + i as f32 * step)
+                    .take_while(|&point| point < next_bar && point < to)
+                    .filter(|&point| point >= from),
+            );
+            bar = next_bar;
+        }
+        points
+    }
+
     pub fn at_beat(&self, pos: BeatPosition) -> StaticTimeSignature {
         match self.changes.first_key_value() {
             Some((first_change, _)) => {
@@ -137,6 +375,168 @@ impl TimeSignature {
     }
 }
 
+impl StaticSwing {
+    pub const MAX_RATIO: f32 = 0.8;
+
+    pub fn new(ratio: f32, unit: Subdivision) -> Self {
+        Self {
+            ratio: ratio.clamp(0.5, Self::MAX_RATIO),
+            unit,
+        }
+    }
+
+    pub fn ratio(self) -> f32 {
+        self.ratio
+    }
+
+    pub fn unit(self) -> Subdivision {
+        self.unit
+    }
+}
+
+impl Default for StaticSwing {
+    fn default() -> Self {
+        Self::new(0.5, Subdivision::straight(8))
+    }
+}
+
+impl From<StaticSwing> for Swing {
+    fn from(rhs: StaticSwing) -> Self {
+        Self {
+            initial: rhs,
+            changes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Swing {
+    fn default() -> Self {
+        StaticSwing::default().into()
+    }
+}
+
+impl Swing {
+    pub fn at_beat(&self, pos: BeatPosition) -> StaticSwing {
+        self.changes
+            .range(..=pos)
+            .next_back()
+            .map_or(self.initial, |(_, &swing)| swing)
+    }
+
+    pub fn add_change(&mut self, position: BeatPosition, swing: StaticSwing) {
+        self.changes.insert(position, swing);
+    }
+
+    pub fn remove_change(&mut self, position: BeatPosition) -> Option<StaticSwing> {
+        self.changes.remove(&position)
+    }
+
+    pub fn changes(&self) -> &BTreeMap<BeatPosition, StaticSwing> {
+        &self.changes
+    }
+
+    /// Runs `warp` on `pos`'s fraction of its swing pair. Pairs start at every bar line; a pair
+    /// cut short by the next bar is left straight
+    fn warp(
+        &self,
+        pos: BeatPosition,
+        time_signatures: &TimeSignature,
+        warp: impl Fn(f32, f32) -> f32,
+    ) -> BeatPosition {
+        let swing = self.at_beat(pos);
+        let bar_start = pos - time_signatures.position_in_bar(pos);
+        let pair = 2.0 * swing.unit.beats(time_signatures.at_beat(bar_start));
+        let pair_start = bar_start + (*(pos - bar_start) / pair).floor() * pair;
+        if pair_start + pair > time_signatures.next_bar(bar_start) {
+            return pos;
+        }
+        pair_start + warp(*(pos - pair_start) / pair, swing.ratio) * pair
+    }
+
+    /// Where a straight position lands once swing is applied
+    pub fn apply(&self, pos: BeatPosition, time_signatures: &TimeSignature) -> BeatPosition {
+        self.warp(pos, time_signatures, |frac, ratio| {
+            if frac < 0.5 {
+                frac * ratio / 0.5
+            } else {
+                ratio + (frac - 0.5) * (1.0 - ratio) / 0.5
+            }
+        })
+    }
+
+    /// Inverse of `apply`
+    pub fn unapply(&self, pos: BeatPosition, time_signatures: &TimeSignature) -> BeatPosition {
+        self.warp(pos, time_signatures, |frac, ratio| {
+            if frac < ratio {
+                frac * 0.5 / ratio
+            } else {
+                0.5 + (frac - ratio) * 0.5 / (1.0 - ratio)
+            }
+        })
+    }
+
+    /// Nearest point of the swung grid
+    pub fn snap(&self, pos: BeatPosition, subdivision: Subdivision, time_signatures: &TimeSignature) -> BeatPosition {
+        let straight = time_signatures.snap(self.unapply(pos, time_signatures), subdivision);
+        self.apply(straight, time_signatures)
+    }
+
+    /// Points of the swung grid in `from..to`
+    pub fn grid(
+        &self,
+        from: BeatPosition,
+        to: BeatPosition,
+        subdivision: Subdivision,
+        time_signatures: &TimeSignature,
+    ) -> Vec<BeatPosition> {
+        time_signatures
+            .grid(self.unapply(from, time_signatures), self.unapply(to, time_signatures), subdivision)
+            .into_iter()
+            .map(|point| self.apply(point, time_signatures))
+            .collect()
+    }
+}
+
+impl Pattern {
+    /// Reads a pattern like `x.x.xx.x`: `x` for a line, anything else for a rest
+    pub fn parse(step: Subdivision, hits: &str) -> Self {
+        Self {
+            step,
+            hits: hits.chars().filter(|c| !c.is_whitespace()).map(|c| c == 'x' || c == 'X').collect(),
+        }
+    }
+
+    /// Positions in `from..to` where the pattern has a hit, with swing applied
+    pub fn generate(
+        &self,
+        from: BeatPosition,
+        to: BeatPosition,
+        time_signatures: &TimeSignature,
+        swing: &Swing,
+    ) -> Vec<BeatPosition> {
+        if !self.hits.contains(&true) {
+            return Vec::new();
+        }
+        let straight_from = swing.unapply(from, time_signatures);
+        let straight_to = swing.unapply(to, time_signatures);
+        let mut bar = straight_from - time_signatures.position_in_bar(straight_from);
+        let mut positions = Vec::new();
+        while bar < straight_to {
+            let next_bar = time_signatures.next_bar(bar);
+            positions.extend(
+                time_signatures
+                    .grid(bar, next_bar, self.step)
+                    .into_iter()
+                    .zip(self.hits.iter().cycle())
+                    .filter(|&(point, &hit)| hit && point >= straight_from && point < straight_to)
+                    .map(|(point, _)| swing.apply(point, time_signatures)),
+            );
+            bar = next_bar;
+        }
+        positions
+    }
+}
+
 impl<T> Default for Lines<T>
 where
     T: Ord,
@@ -173,6 +573,53 @@ where
     }
 }
 
+impl Lines<Duration> {
+    /// Moves every line within `tolerance` of the (swung) grid `strength` of the way to its
+    /// nearest grid point; 1 snaps it exactly, 0 leaves it alone
+    pub fn quantize(
+        &self,
+        beat_rate: &BeatRate,
+        time_signatures: &TimeSignature,
+        swing: &Swing,
+        subdivision: Subdivision,
+        tolerance: Duration,
+        strength: f32,
+    ) -> Quantized {
+        let strength = strength.clamp(0.0, 1.0);
+        let mut lines = Lines::new();
+        let mut unsnapped = Vec::new();
+        for &time in &self.positions {
+            let pos = beat_rate.beat_at(time);
+            let snapped = swing.snap(pos, subdivision, time_signatures);
+            let snapped_time = beat_rate.time_at(snapped);
+            let distance = if snapped_time > time { snapped_time - time } else { time - snapped_time };
+            if distance <= tolerance {
+                lines.insert(pos + (snapped - pos) * strength);
+            } else {
+                lines.insert(pos);
+                unsnapped.push(time);
+            }
+        }
+        Quantized { lines, unsnapped }
+    }
+}
+
+/// A steady tempo with a bar line `offset` seconds into the song (negative: before the song
+/// starts); beat 0 stays at the start of the song
+pub fn steady_tempo(
+    rate: StaticBeatRate,
+    signature: StaticTimeSignature,
+    offset: f64,
+) -> (BeatRate, TimeSignature) {
+    let bar = rate.0.as_secs_f64() * signature.numerator as f64;
+    let first_bar = offset.rem_euclid(bar) / rate.0.as_secs_f64();
+    let mut time_signatures: TimeSignature = signature.into();
+    if first_bar > 0.0 {
+        time_signatures.add_change(Float(first_bar as f32), signature);
+    }
+    (rate.into(), time_signatures)
+}
+
 // #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 // struct SamplesSlice<'a, T: Sample, const N: usize> {
 //     samples: &'a [T],
@@ -234,6 +681,71 @@ mod tests {
         assert_eq!(rate.at_beat(6.0.into()), StaticBeatRate::from_bpm(120.0));
     }
 
+    #[test]
+    fn time_at_with_changes() {
+        let mut rate: BeatRate = StaticBeatRate::from_bpm(120.0).into();
+        rate.add_change(4.0.into(), StaticBeatRate::from_bpm(60.0));
+        assert_eq!(rate.time_at(2.0.into()), Duration::from_secs(1));
+        assert_eq!(rate.time_at(6.0.into()), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn beat_at_inverts_time_at() {
+        let mut rate: BeatRate = StaticBeatRate::from_bpm(120.0).into();
+        rate.add_change(4.0.into(), StaticBeatRate::from_bpm(60.0));
+        rate.add_change(8.0.into(), StaticBeatRate::from_bpm(150.0));
+        for beat in [0.0, 3.5, 4.0, 7.25, 12.0] {
+            assert!((rate.beat_at(rate.time_at(beat.into())) - beat).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn snap_straight_and_triplet() {
+        let sig: TimeSignature = StaticTimeSignature::new(4, 4).into();
+        assert_eq!(sig.snap(1.3.into(), Subdivision::straight(8)), Float(1.5));
+        assert_eq!(sig.snap(1.3.into(), Subdivision::triplet(8)), Float(4.0 / 3.0));
+        assert_eq!(sig.snap(3.9.into(), Subdivision::dotted(4)), Float(4.0));
+    }
+
+    #[test]
+    fn snap_restarts_at_signature_change() {
+        let mut sig: TimeSignature = StaticTimeSignature::new(4, 4).into();
+        sig.add_change(5.0.into(), StaticTimeSignature::new(6, 8));
+        // the 4/4 bar starting at 4 is cut short by the change at 5
+        assert_eq!(sig.snap(4.9.into(), Subdivision::straight(1)), Float(5.0));
+        // a quarter note is two beats in 6/8
+        assert_eq!(sig.snap(7.2.into(), Subdivision::straight(4)), Float(7.0));
+        assert_eq!(sig.bars(0.0.into(), 12.0.into()), [0.0, 4.0, 5.0, 11.0].map(Float));
+    }
+
+    #[test]
+    fn swing_moves_offbeats_only() {
+        let sig: TimeSignature = StaticTimeSignature::new(4, 4).into();
+        let swing: Swing = StaticSwing::new(2.0 / 3.0, Subdivision::straight(8)).into();
+        assert_eq!(swing.apply(1.0.into(), &sig), Float(1.0));
+        assert!((swing.apply(1.5.into(), &sig) - (1.0 + 2.0 / 3.0)).abs() < 1e-5);
+        for pos in [0.1, 0.5, 1.3, 2.75, 3.9] {
+            assert!((swing.unapply(swing.apply(pos.into(), &sig), &sig) - pos).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn swing_snaps_to_swung_offbeat() {
+        let sig: TimeSignature = StaticTimeSignature::new(4, 4).into();
+        let mut swing = Swing::default();
+        swing.add_change(4.0.into(), StaticSwing::new(0.75, Subdivision::straight(8)));
+        assert_eq!(swing.snap(0.6.into(), Subdivision::straight(8), &sig), Float(0.5));
+        assert_eq!(swing.snap(4.6.into(), Subdivision::straight(8), &sig), Float(4.75));
+    }
+
+    #[test]
+    fn pattern_repeats_each_bar() {
+        let sig: TimeSignature = StaticTimeSignature::new(4, 4).into();
+        let pattern = Pattern::parse(Subdivision::straight(4), "x.xx");
+        let positions = pattern.generate(0.0.into(), 8.0.into(), &sig, &Swing::default());
+        assert_eq!(positions, [0.0, 2.0, 3.0, 4.0, 6.0, 7.0].map(Float));
+    }
+
     #[test]
     fn rate_at_change() {
         let mut rate: BeatRate = StaticBeatRate::from_bpm(100.0).into();
@@ -241,4 +753,29 @@ mod tests {
         rate.add_change(10.0.into(), StaticBeatRate::from_bpm(140.0));
         assert_eq!(rate.at_beat(5.0.into()), StaticBeatRate::from_bpm(120.0));
     }
+
+    #[test]
+    fn quantize_snaps_within_tolerance() {
+        let (beat_rate, time_signatures) = steady_tempo(StaticBeatRate::from_bpm(120.0), StaticTimeSignature::new(4, 4), 0.25);
+        assert_eq!(time_signatures.changes().keys().copied().collect::<Vec<_>>(), [Float(0.5)]);
+        // a bar line 1.75s before the song puts the next one at 0.25s too
+        let (_, early) = steady_tempo(StaticBeatRate::from_bpm(120.0), StaticTimeSignature::new(4, 4), -1.75);
+        assert_eq!(early.changes(), time_signatures.changes());
+
+        let mut raw = Lines::new();
+        for ms in [760, 1240, 1400] {
+            raw.insert(Duration::from_millis(ms));
+        }
+        let quarters = Subdivision::straight(4);
+        let tolerance = Duration::from_millis(20);
+        let full = raw.quantize(&beat_rate, &time_signatures, &Swing::default(), quarters, tolerance, 1.0);
+        // quarters fall at 250, 750, 1250ms...; 1400 is 150ms off
+        let positions: Vec<f32> = full.lines.get_positions().iter().map(|p| p.0).collect();
+        assert_eq!(positions, [1.5, 2.5, 2.8]);
+        assert_eq!(full.unsnapped, [Duration::from_millis(1400)]);
+
+        let half = raw.quantize(&beat_rate, &time_signatures, &Swing::default(), quarters, tolerance, 0.5);
+        let positions: Vec<f32> = half.lines.get_positions().iter().map(|p| (p.0 * 100.0).round() / 100.0).collect();
+        assert_eq!(positions, [1.51, 2.49, 2.8]);
+    }
 }
@@ -0,0 +1,167 @@
+use crate::music::{BeatRate, StaticBeatRate, StaticTimeSignature, TimeSignature};
+use crate::{Color, GdlData};
+use ordered_float::OrderedFloat as Float;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum OsuError {
+    #[error("Line {0} of the beatmap is malformed")]
+    Malformed(usize),
+    #[error("Beatmap has no uninherited timing points")]
+    NoTiming,
+}
+
+/// An uninherited ("red") timing point
+struct TimingPoint {
+    time: f64, // ms
+    beat_length: f64, // ms
+    meter: u32,
+}
+
+/// Reads the timing of an osu! beatmap (`.osu`): every uninherited timing point starts a new
+/// bar with its own tempo and meter. Hit objects become lines of `hit_objects`' colour, if given.
+pub fn import(text: &str, hit_objects: Option<Color>) -> Result<GdlData, OsuError> {
+    let mut section = "";
+    let mut points = Vec::new();
+    let mut hit_times = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            section = &line[1..line.len() - 1];
+            continue;
+        }
+        let malformed = || OsuError::Malformed(idx + 1);
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        match section {
+            "TimingPoints" => {
+                let number = |i: usize| fields.get(i).and_then(|f| f.parse::<f64>().ok());
+                let time = number(0).ok_or_else(malformed)?;
+                let beat_length = number(1).ok_or_else(malformed)?;
+                // old beatmaps leave out the flag; inherited points have negative beat lengths
+                let uninherited = fields.get(6).map_or(beat_length > 0.0, |&flag| flag == "1");
+                if uninherited && beat_length > 0.0 {
+                    let meter = number(2).map_or(4, |meter| meter.max(1.0) as u32);
+                    points.push(TimingPoint { time, beat_length, meter });
+                }
+            }
+            "HitObjects" if hit_objects.is_some() => {
+                let time = fields.get(2).and_then(|f| f.parse::<f64>().ok()).ok_or_else(malformed)?;
+                hit_times.push(time);
+            }
+            _ => {}
+        }
+    }
+    points.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let first = points.first().ok_or(OsuError::NoTiming)?;
+    let ms = |time: f64| Duration::from_secs_f64(time.max(0.0) / 1000.0);
+    let rate = |point: &TimingPoint| StaticBeatRate::from_beat_length(ms(point.beat_length));
+    let signature = |point: &TimingPoint| StaticTimeSignature::new(point.meter, 4);
+
+    // beat 0 is the start of the song, so the first point's bars are extended back to it
+    let first_time = first.time.rem_euclid(first.beat_length * first.meter as f64);
+    // (time, beat) of every point, worked out in f64 so whole beats stay whole
+    let mut anchors = Vec::with_capacity(points.len());
+    for (idx, point) in points.iter().enumerate() {
+        let anchor = match anchors.last() {
+            None => (first_time, first_time / point.beat_length),
+            Some(&(time, pos)) => (point.time, pos + (point.time - time) / points[idx - 1].beat_length),
+        };
+        anchors.push(anchor);
+    }
+    let beat_at = |time: f64| {
+        let idx = anchors.iter().rposition(|&(t, _)| t <= time).unwrap_or(0);
+        let (anchor_time, anchor_pos) = anchors[idx];
+        Float((anchor_pos + (time - anchor_time) / points[idx].beat_length) as f32)
+    };
+
+    let mut beat_rate: BeatRate = rate(first).into();
+    let mut time_signatures: TimeSignature = signature(first).into();
+    for (idx, (point, &(time, _))) in points.iter().zip(&anchors).enumerate() {
+        let pos = beat_at(time);
+        if idx > 0 {
+            beat_rate.add_change(pos, rate(point));
+        }
+        if pos > Float(0.0) {
+            time_signatures.add_change(pos, signature(point));
+        }
+    }
+
+    let mut data = GdlData {
+        beat_rate,
+        time_signatures,
+        ..Default::default()
+    };
+    if let Some(color) = hit_objects {
+        for time in hit_times {
+            data.lines_mut(color).insert(beat_at(time));
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEATMAP: &str = "osu file format v14
+
+[General]
+AudioFilename: audio.mp3
+
+[TimingPoints]
+500,500,4,2,1,60,1,0
+2500,-50,4,2,1,60,0,0
+4500,250,3,2,1,60,1,0
+
+[HitObjects]
+256,192,500,1,0,0:0:0:0:
+256,192,1500,1,0,0:0:0:0:
+256,192,4750,5,0,0:0:0:0:
+";
+
+    fn positions(lines: &crate::music::Lines) -> Vec<f32> {
+        lines.get_positions().iter().map(|p| p.0).collect()
+    }
+
+    #[test]
+    fn imports_red_lines() {
+        let data = import(BEATMAP, None).unwrap();
+        assert_eq!(data.beat_rate.at_beat(Float(0.0)).beat_length(), Duration::from_millis(500));
+        // the green line at 2500 doesn't change anything
+        assert_eq!(data.beat_rate.changes().len(), 1);
+        assert_eq!(data.beat_rate.at_beat(Float(9.0)).beat_length(), Duration::from_millis(250));
+        // the first bar starts at 500ms, a beat in
+        assert_eq!(data.time_signatures.at_beat(Float(0.0)), StaticTimeSignature::new(4, 4));
+        let changes: Vec<_> = data.time_signatures.changes().iter().map(|(p, s)| (p.0, *s)).collect();
+        assert_eq!(
+            changes,
+            [(1.0, StaticTimeSignature::new(4, 4)), (9.0, StaticTimeSignature::new(3, 4))]
+        );
+        assert!(data.green_lines.empty());
+    }
+
+    #[test]
+    fn hit_objects_become_lines() {
+        let data = import(BEATMAP, Some(Color::Orange)).unwrap();
+        assert_eq!(positions(&data.orange_lines), [1.0, 3.0, 10.0]);
+    }
+
+    #[test]
+    fn first_point_before_song_start() {
+        let data = import("[TimingPoints]\n-300,400,4,1,0,100,1,0\n", None).unwrap();
+        let changes: Vec<f32> = data.time_signatures.changes().keys().map(|p| p.0).collect();
+        // bars of 1600ms, the first one starting at 1300ms
+        assert_eq!(changes, [3.25]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(import("[TimingPoints]\n100,-100,4,1,0,100,0,0\n", None), Err(OsuError::NoTiming)));
+        assert!(matches!(import("[General]\n\n[TimingPoints]\nnonsense\n", None), Err(OsuError::Malformed(4))));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::audio::{self, StretchMode, Track};
+use rodio::Source;
+use std::time::{Duration, Instant};
+
+/// Where played audio ends up
+pub trait Output {
+    /// Replaces whatever is playing with `source`
+    fn play(&mut self, source: Box<dyn Source<Item = f32> + Send>);
+    fn stop(&mut self);
+    /// True if nothing will actually be heard
+    fn silent(&self) -> bool {
+        false
+    }
+}
+
+/// Plays through the system's default audio device
+pub struct DeviceOutput {
+    _stream: rodio::OutputStream, // the handle only works while this is alive
+    handle: rodio::OutputStreamHandle,
+    sink: Option<rodio::Sink>,
+}
+
+/// Throws the audio away; for running without an audio device, and for tests
+#[derive(Default)]
+pub struct NullOutput;
+
+/// Plays a track through an `Output` and keeps track of the playhead, which moves on
+/// even when nothing can be heard
+pub struct Player {
+    track: Track,
+    output: Box<dyn Output>,
+    speed: f32,
+    stretch_mode: StretchMode,
+    /// (moment playback started, song position it started from)
+    playback_start: Option<(Instant, Duration)>,
+    paused_at: Duration,
+}
+
+impl DeviceOutput {
+    pub fn try_default() -> Result<Self, rodio::StreamError> {
+        let (stream, handle) = rodio::OutputStream::try_default()?;
+        Ok(Self { _stream: stream, handle, sink: None })
+    }
+}
+
+impl Output for DeviceOutput {
+    fn play(&mut self, source: Box<dyn Source<Item = f32> + Send>) {
+        // a stopped sink stays stopped, so every playback gets a fresh one
+        match rodio::Sink::try_new(&self.handle) {
+            Ok(sink) => {
+                sink.append(source);
+                self.sink = Some(sink);
+            }
+            Err(e) => log::error!("Couldn't create audio sink: {e}"),
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+    }
+}
+
+impl Output for NullOutput {
+    fn play(&mut self, _source: Box<dyn Source<Item = f32> + Send>) {}
+
+    fn stop(&mut self) {}
+
+    fn silent(&self) -> bool {
+        true
+    }
+}
+
+impl Player {
+    pub fn new(track: Track, output: Box<dyn Output>) -> Self {
+        Self {
+            track,
+            output,
+            speed: 1.0,
+            stretch_mode: Default::default(),
+            playback_start: None,
+            paused_at: Duration::ZERO,
+        }
+    }
+
+    /// A player on the default audio device, or a silent one if there is none (or `audio` is off)
+    pub fn with_default_output(track: Track, audio: bool) -> Self {
+        let output: Box<dyn Output> = match audio.then(DeviceOutput::try_default) {
+            Some(Ok(device)) => Box::new(device),
+            Some(Err(e)) => {
+                log::warn!("No audio output, playing silently: {e}");
+                Box::new(NullOutput)
+            }
+            None => Box::new(NullOutput),
+        };
+        Self::new(track, output)
+    }
+
+    pub fn length(&self) -> Duration {
+        self.track.length()
+    }
+
+    pub fn silent(&self) -> bool {
+        self.output.silent()
+    }
+
+    pub fn play_from(&mut self, position: Duration) {
+        let source = self.track.source_from(position);
+        let source: Box<dyn Source<Item = f32> + Send> = match self.stretch_mode {
+            _ if self.speed == 1.0 => Box::new(source.convert_samples::<f32>()),
+            StretchMode::PreservePitch => Box::new(audio::TimeStretch::new(source, self.speed)),
+            StretchMode::Resample => Box::new(source.speed(self.speed).convert_samples::<f32>()),
+        };
+        self.output.play(source);
+        self.playback_start = Some((Instant::now(), position));
+    }
+
+    /// Plays on from the playhead, or from the start once the song has played to the end
+    pub fn resume(&mut self) {
+        let position = self.position();
+        self.play_from(if position >= self.length() { Duration::ZERO } else { position });
+    }
+
+    pub fn stop(&mut self) {
+        self.paused_at = self.position();
+        self.playback_start = None;
+        self.output.stop();
+    }
+
+    pub fn playing(&self) -> bool {
+        self.playback_start.is_some() && self.position() < self.length()
+    }
+
+    /// Position of the playhead in song time, regardless of playback speed
+    pub fn position(&self) -> Duration {
+        match self.playback_start {
+            Some((started, from)) => (from + started.elapsed().mul_f32(self.speed)).min(self.length()),
+            None => self.paused_at,
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn stretch_mode(&self) -> StretchMode {
+        self.stretch_mode
+    }
+
+    pub fn set_speed(&mut self, speed: f32, mode: StretchMode) {
+        let speed = speed.clamp(audio::MIN_SPEED, audio::MAX_SPEED);
+        if speed == self.speed && mode == self.stretch_mode {
+            return;
+        }
+        let restart = self.playback_start.is_some();
+        let position = self.position();
+        if restart {
+            self.stop();
+        }
+        self.speed = speed;
+        self.stretch_mode = mode;
+        if restart {
+            self.play_from(position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Remembers how many samples each playback would have produced
+    #[derive(Clone, Default)]
+    struct RecordingOutput(Rc<RefCell<Vec<usize>>>);
+
+    impl Output for RecordingOutput {
+        fn play(&mut self, source: Box<dyn Source<Item = f32> + Send>) {
+            self.0.borrow_mut().push(source.count());
+        }
+
+        fn stop(&mut self) {}
+    }
+
+    fn track() -> Track {
+        Track::from_source(SamplesBuffer::new(1, 1000, vec![0i16; 4000]), Duration::from_secs(4), 0.0)
+    }
+
+    #[test]
+    fn playhead_moves_without_audio() {
+        let mut player = Player::new(track(), Box::new(NullOutput));
+        assert!(player.silent());
+        player.play_from(Duration::from_secs(1));
+        assert!(player.playing());
+        assert!(player.position() >= Duration::from_secs(1));
+
+        player.stop();
+        let paused = player.position();
+        assert!(!player.playing());
+        assert!(paused >= Duration::from_secs(1) && paused < Duration::from_secs(2));
+        assert_eq!(player.position(), paused);
+    }
+
+    #[test]
+    fn plays_from_position() {
+        let output = RecordingOutput::default();
+        let mut player = Player::new(track(), Box::new(output.clone()));
+        player.play_from(Duration::from_secs(3));
+        assert_eq!(*output.0.borrow(), [1000]);
+    }
+
+    #[test]
+    fn resume_restarts_after_the_end() {
+        let output = RecordingOutput::default();
+        let mut player = Player::new(track(), Box::new(output.clone()));
+        player.play_from(Duration::from_secs(4));
+        assert!(!player.playing());
+        assert_eq!(player.position(), player.length());
+
+        player.resume();
+        assert!(player.playing());
+        assert!(player.position() < Duration::from_secs(1));
+        assert_eq!(*output.0.borrow(), [0, 4000]);
+    }
+
+    #[test]
+    fn resume_continues_from_pause() {
+        let output = RecordingOutput::default();
+        let mut player = Player::new(track(), Box::new(output.clone()));
+        player.play_from(Duration::from_secs(3));
+        player.stop();
+        player.resume();
+        assert!(player.position() >= Duration::from_secs(3));
+        assert_eq!(output.0.borrow().len(), 2);
+        assert!(output.0.borrow()[1] <= 1000);
+    }
+
+    #[test]
+    fn speed_change_restarts_only_while_playing() {
+        let output = RecordingOutput::default();
+        let mut player = Player::new(track(), Box::new(output.clone()));
+        player.set_speed(2.0, StretchMode::Resample);
+        assert!(output.0.borrow().is_empty());
+
+        player.play_from(Duration::ZERO);
+        player.set_speed(0.5, StretchMode::Resample);
+        assert_eq!(output.0.borrow().len(), 2);
+        assert_eq!(player.speed(), 0.5);
+    }
+}
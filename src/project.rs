@@ -0,0 +1,298 @@
+use crate::gd;
+use crate::GdlData;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Bumped whenever the layout of `ProjectFile` changes
+pub const VERSION: u32 = 1;
+
+/// Identifies the level a project belongs to; a new revision or song gets its own project
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelKey {
+    pub name: String,
+    pub revision: Option<i64>,
+    pub song: gd::Song,
+}
+
+/// Pipedash's own save format, keeping the beat-based editing data of one level
+#[derive(Serialize, Deserialize)]
+pub struct ProjectFile {
+    pub version: u32,
+    pub level: LevelKey,
+    pub data: GdlData,
+}
+
+#[derive(Debug, Error)]
+pub enum ProjectError {
+    #[error("Couldn't access project file")]
+    Io(#[from] std::io::Error),
+    #[error("Project file is corrupted")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("Couldn't write project file")]
+    Serialize(#[from] ron::Error),
+    #[error("Project was saved by a newer version of Pipedash (format {0})")]
+    NewerVersion(u32),
+    #[error("Project file belongs to a different level")]
+    WrongLevel,
+    #[error("Project file has a time signature with empty bars")]
+    InvalidTimeSignature,
+}
+
+impl From<&gd::Level> for LevelKey {
+    fn from(level: &gd::Level) -> Self {
+        Self {
+            name: level.name().into(),
+            revision: level.revision(),
+            song: level.song(),
+        }
+    }
+}
+
+impl LevelKey {
+    /// Readable, filesystem-safe file stem; the hash keeps levels with similar names apart
+    fn file_stem(&self) -> String {
+        let readable: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let digest = md5::compute(ron::to_string(self).unwrap_or_default());
+        format!("{readable}-{digest:x}")
+    }
+}
+
+pub fn projects_dir() -> PathBuf {
+    crate::project_dirs().data_dir().join("projects")
+}
+
+pub fn project_path(level: &LevelKey) -> PathBuf {
+    projects_dir().join(level.file_stem()).with_extension("ron")
+}
+
+/// Loads the project for `level`, if one has been saved
+pub fn load(level: &LevelKey) -> Result<Option<GdlData>, ProjectError> {
+    load_from(&project_path(level), level)
+}
+
+fn load_from(path: &Path, level: &LevelKey) -> Result<Option<GdlData>, ProjectError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = parse(&fs::read_to_string(path)?)?;
+    if &file.level != level {
+        return Err(ProjectError::WrongLevel);
+    }
+    Ok(Some(file.data))
+}
+
+/// Checks the format version before reading the rest, which a newer version may have changed,
+/// and that the tempo map can be worked with
+fn parse(contents: &str) -> Result<ProjectFile, ProjectError> {
+    let header: VersionHeader = ron::from_str(contents)?;
+    if header.version > VERSION {
+        return Err(ProjectError::NewerVersion(header.version));
+    }
+    let file: ProjectFile = ron::from_str(contents)?;
+    if !file.data.time_signatures.is_valid() {
+        return Err(ProjectError::InvalidTimeSignature);
+    }
+    Ok(file)
+}
+
+pub fn save(level: &LevelKey, data: &GdlData) -> Result<(), ProjectError> {
+    fs::create_dir_all(projects_dir())?;
+    write_project(&project_path(level), level, data)
+}
+
+fn write_project(path: &Path, level: &LevelKey, data: &GdlData) -> Result<(), ProjectError> {
+    let contents = ron::ser::to_string_pretty(
+        &SavedProject { version: VERSION, level, data },
+        ron::ser::PrettyConfig::default(),
+    )?;
+    // write next to the real file first so a crash mid-write can't eat the project
+    let temp_path = path.with_extension("ron.tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+fn autosave_path() -> PathBuf {
+    crate::project_dirs().data_dir().join("autosave.ron")
+}
+
+/// Exists while Pipedash is running; finding it at startup means the last session crashed
+fn session_marker_path() -> PathBuf {
+    crate::project_dirs().data_local_dir().join("session.lock")
+}
+
+pub fn autosave(level: &LevelKey, data: &GdlData) -> Result<(), ProjectError> {
+    fs::create_dir_all(crate::project_dirs().data_dir())?;
+    write_project(&autosave_path(), level, data)
+}
+
+/// Marks the start of a session, returning what the previous one left behind if it didn't
+/// shut down cleanly
+pub fn start_session() -> Option<ProjectFile> {
+    start_session_at(&session_marker_path(), &autosave_path())
+}
+
+fn start_session_at(marker: &Path, autosave: &Path) -> Option<ProjectFile> {
+    let unclean = marker.exists();
+    if let Err(e) = fs::write(marker, "") {
+        log::warn!("Couldn't create session marker: {e}");
+    }
+    if !unclean {
+        return None;
+    }
+    log::warn!("Previous session didn't shut down cleanly");
+    let recovered = fs::read_to_string(autosave)
+        .map_err(ProjectError::from)
+        .and_then(|contents| parse(&contents));
+    match recovered {
+        Ok(file) => Some(file),
+        Err(ProjectError::NewerVersion(version)) => {
+            log::warn!("Autosave has unknown format {version}");
+            None
+        }
+        Err(e) => {
+            log::info!("Nothing to recover: {e}");
+            None
+        }
+    }
+}
+
+/// Marks a clean shutdown; the autosave is dropped along with it
+pub fn end_session() {
+    end_session_at(&session_marker_path(), &autosave_path())
+}
+
+fn end_session_at(marker: &Path, autosave: &Path) {
+    for path in [autosave, marker] {
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Couldn't remove {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// Borrowing twin of `ProjectFile`, so saving doesn't need to clone the editor data
+#[derive(Serialize)]
+struct SavedProject<'a> {
+    version: u32,
+    level: &'a LevelKey,
+    data: &'a GdlData,
+}
+
+/// Just enough of a `ProjectFile` to tell which format the rest is in
+#[derive(Deserialize)]
+struct VersionHeader {
+    version: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{StaticBeatRate, StaticSwing, StaticTimeSignature, Subdivision};
+    use crate::test_util::ScratchDir;
+    use crate::CustomSong;
+
+    fn level() -> LevelKey {
+        LevelKey { name: "Stereo Madness 2".into(), revision: Some(3), song: gd::Song::Newgrounds { id: 803223 } }
+    }
+
+    #[test]
+    fn newer_version_is_reported_before_parsing() {
+        let contents = "(version: 99, level: (name: \"Future\"), layout_from_the_future: [1, 2, 3])";
+        assert!(matches!(parse(contents), Err(ProjectError::NewerVersion(99))));
+        assert!(matches!(parse("(version: 1, level: ())"), Err(ProjectError::Parse(_))));
+    }
+
+    #[test]
+    fn recovers_autosave_after_crash() {
+        let dir = ScratchDir::new("session");
+        let marker = dir.path().join("session.lock");
+        let autosave = dir.path().join("autosave.ron");
+        let mut data = GdlData::default();
+        data.green_lines.insert(2.0.into());
+        data.orange_lines.insert(5.5.into());
+
+        // a first run finds nothing to recover
+        assert!(start_session_at(&marker, &autosave).is_none());
+        assert!(marker.exists());
+        write_project(&autosave, &level(), &data).unwrap();
+        let written = parse(&fs::read_to_string(&autosave).unwrap()).unwrap();
+        assert_eq!(written.level, level());
+
+        // the marker is still there, as if the run crashed
+        let recovered = start_session_at(&marker, &autosave).unwrap();
+        assert_eq!(recovered.version, VERSION);
+        assert_eq!(recovered.level, level());
+        assert_eq!(recovered.data.green_lines.get_positions(), data.green_lines.get_positions());
+        assert_eq!(recovered.data.orange_lines.get_positions(), data.orange_lines.get_positions());
+    }
+
+    #[test]
+    fn clean_shutdown_leaves_nothing_to_recover() {
+        let dir = ScratchDir::new("clean-session");
+        let marker = dir.path().join("session.lock");
+        let autosave = dir.path().join("autosave.ron");
+        assert!(start_session_at(&marker, &autosave).is_none());
+        write_project(&autosave, &level(), &GdlData::default()).unwrap();
+
+        end_session_at(&marker, &autosave);
+        assert!(!marker.exists());
+        assert!(!autosave.exists());
+        assert!(start_session_at(&marker, &autosave).is_none());
+        // ending again, with nothing left to remove, is fine
+        end_session_at(&marker, &autosave);
+    }
+
+    #[test]
+    fn project_round_trips() {
+        let dir = ScratchDir::new("project");
+        let path = dir.path().join("level.ron");
+        let mut data = GdlData::default();
+        data.green_lines.insert(1.0.into());
+        data.yellow_lines.insert(2.5.into());
+        data.orange_lines.insert(8.0.into());
+        data.beat_rate.add_change(4.0.into(), StaticBeatRate::from_bpm(150.0));
+        data.time_signatures.add_change(4.0.into(), StaticTimeSignature::new(7, 8));
+        data.swing.add_change(8.0.into(), StaticSwing::new(2.0 / 3.0, Subdivision::straight(8)));
+        data.custom_song = Some(CustomSong { path: "song.ogg".into(), offset: -0.25 });
+
+        assert!(load_from(&path, &level()).unwrap().is_none());
+        write_project(&path, &level(), &data).unwrap();
+        let loaded = load_from(&path, &level()).unwrap().unwrap();
+        assert_eq!(loaded.green_lines.get_positions(), data.green_lines.get_positions());
+        assert_eq!(loaded.yellow_lines.get_positions(), data.yellow_lines.get_positions());
+        assert_eq!(loaded.orange_lines.get_positions(), data.orange_lines.get_positions());
+        assert_eq!(loaded.beat_rate.changes(), data.beat_rate.changes());
+        assert_eq!(loaded.beat_rate.at_beat(0.0.into()), data.beat_rate.at_beat(0.0.into()));
+        assert_eq!(loaded.time_signatures.changes(), data.time_signatures.changes());
+        assert_eq!(loaded.time_signatures.at_beat(0.0.into()), data.time_signatures.at_beat(0.0.into()));
+        assert_eq!(loaded.swing.changes(), data.swing.changes());
+        let custom_song = loaded.custom_song.unwrap();
+        assert_eq!((custom_song.path.to_str(), custom_song.offset), (Some("song.ogg"), -0.25));
+
+        let other = LevelKey { revision: Some(4), ..level() };
+        assert!(matches!(load_from(&path, &other), Err(ProjectError::WrongLevel)));
+    }
+
+    #[test]
+    fn empty_bars_are_rejected() {
+        let dir = ScratchDir::new("empty-bars");
+        let path = dir.path().join("level.ron");
+        for signature in [StaticTimeSignature::new(0, 4), StaticTimeSignature::new(4, 0)] {
+            let mut data = GdlData::default();
+            data.time_signatures.add_change(4.0.into(), signature);
+            write_project(&path, &level(), &data).unwrap();
+            assert!(matches!(load_from(&path, &level()), Err(ProjectError::InvalidTimeSignature)));
+        }
+        let data = GdlData { time_signatures: StaticTimeSignature::new(0, 4).into(), ..GdlData::default() };
+        write_project(&path, &level(), &data).unwrap();
+        assert!(matches!(load_from(&path, &level()), Err(ProjectError::InvalidTimeSignature)));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::gd;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Newgrounds song info from earlier requests, by the song info URL of the server it came from
+/// and song id; private servers reuse ids for other songs
+type Cache = BTreeMap<String, BTreeMap<i64, gd::SongResponse>>;
+
+fn cache_path() -> PathBuf {
+    crate::project_dirs().cache_dir().join("songs.ron")
+}
+
+fn read_cache(path: &Path) -> Cache {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| {
+            ron::from_str(&contents)
+                .map_err(|e| log::warn!("Song cache unreadable, ignoring it: {e}"))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+fn write_cache(path: &Path, cache: &Cache) {
+    let result = fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
+        .map_err(|e| e.to_string())
+        .and_then(|_| ron::to_string(cache).map_err(|e| e.to_string()))
+        .and_then(|contents| fs::write(path, contents).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        log::warn!("Couldn't write song cache: {e}");
+    }
+}
+
+pub fn cached(server: &gd::Server, id: i64) -> Option<gd::SongResponse> {
+    cached_in(&cache_path(), server, id)
+}
+
+fn cached_in(path: &Path, server: &gd::Server, id: i64) -> Option<gd::SongResponse> {
+    read_cache(path).remove(&server.song_info_url)?.remove(&id)
+}
+
+/// Song info for `song`, only asking the server if it isn't cached yet
+pub fn lookup(song: &gd::Song, server: &gd::Server) -> Result<gd::SongResponse, gd::SongRequestError> {
+    lookup_in(&cache_path(), song, server)
+}
+
+fn lookup_in(path: &Path, song: &gd::Song, server: &gd::Server) -> Result<gd::SongResponse, gd::SongRequestError> {
+    match song {
+        gd::Song::Newgrounds { id } => match cached_in(path, server, *id) {
+            Some(response) => Ok(response),
+            None => refresh_in(path, song, server),
+        },
+        _ => Err(gd::SongRequestError::NotNewgrounds),
+    }
+}
+
+/// Asks the server for the song info and updates the cache; falls back to the cached info when
+/// the server can't be reached
+pub fn refresh(song: &gd::Song, server: &gd::Server) -> Result<gd::SongResponse, gd::SongRequestError> {
+    refresh_in(&cache_path(), song, server)
+}
+
+fn refresh_in(path: &Path, song: &gd::Song, server: &gd::Server) -> Result<gd::SongResponse, gd::SongRequestError> {
+    let gd::Song::Newgrounds { id } = *song else {
+        return Err(gd::SongRequestError::NotNewgrounds);
+    };
+    let mut cache = read_cache(path);
+    let songs = cache.entry(server.song_info_url.clone()).or_default();
+    match song.get_response(server) {
+        Ok(response) => {
+            songs.insert(id, response.clone());
+            write_cache(path, &cache);
+            Ok(response)
+        }
+        Err(e @ gd::SongRequestError::ConnectionFailure(_)) => songs.remove(&id).ok_or(e).map(|response| {
+            log::info!("Offline, using cached info for song {id}");
+            response
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, ScratchDir};
+    use std::net::TcpListener;
+
+    const SONG: gd::Song = gd::Song::Newgrounds { id: 803223 };
+
+    fn song_info(name: &str) -> String {
+        format!("1~|~803223~|~2~|~{name}~|~4~|~Xtrullor~|~5~|~9.79~|~10~|~http%3A%2F%2Fexample.com%2F{name}.mp3")
+    }
+
+    /// A server answering a single request with song info for `name`
+    fn stand_in(name: &str) -> gd::Server {
+        let body = song_info(name);
+        let (root, _form) = test_util::stand_in(body.clone(), body.len());
+        gd::Server { song_info_url: format!("{root}/database/getGJSongInfo.php"), secret: "test-secret".into() }
+    }
+
+    /// A server nobody answers at
+    fn unreachable() -> gd::Server {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        gd::Server { song_info_url: format!("http://127.0.0.1:{port}/database/getGJSongInfo.php"), secret: "test-secret".into() }
+    }
+
+    /// A cache holding `name`'s info for the song under `server`
+    fn cache_with(server: &gd::Server, name: &str) -> Cache {
+        let response = SONG.get_response(&stand_in(name)).unwrap();
+        BTreeMap::from([(server.song_info_url.clone(), BTreeMap::from([(803223, response)]))])
+    }
+
+    #[test]
+    fn falls_back_to_cache_when_offline() {
+        let dir = ScratchDir::new("song-cache-offline");
+        let path = dir.path().join("songs.ron");
+        let server = unreachable();
+        assert!(matches!(refresh_in(&path, &SONG, &server), Err(gd::SongRequestError::ConnectionFailure(_))));
+
+        write_cache(&path, &cache_with(&server, "Supernova"));
+        let response = refresh_in(&path, &SONG, &server).unwrap();
+        assert_eq!(response.name(), Some("Supernova"));
+    }
+
+    #[test]
+    fn refresh_replaces_stale_info() {
+        let dir = ScratchDir::new("song-cache-stale");
+        let path = dir.path().join("songs.ron");
+        let server = stand_in("Renamed");
+        write_cache(&path, &cache_with(&server, "Old"));
+
+        // a lookup doesn't ask the server while the song is cached
+        assert_eq!(lookup_in(&path, &SONG, &server).unwrap().name(), Some("Old"));
+        assert_eq!(refresh_in(&path, &SONG, &server).unwrap().name(), Some("Renamed"));
+        assert_eq!(cached_in(&path, &server, 803223).unwrap().name(), Some("Renamed"));
+    }
+
+    #[test]
+    fn servers_are_kept_apart() {
+        let dir = ScratchDir::new("song-cache-servers");
+        let path = dir.path().join("songs.ron");
+        let official = unreachable();
+        write_cache(&path, &cache_with(&official, "Official"));
+
+        let private = stand_in("Private");
+        assert_eq!(lookup_in(&path, &SONG, &private).unwrap().name(), Some("Private"));
+        assert_eq!(cached_in(&path, &official, 803223).unwrap().name(), Some("Official"));
+        assert_eq!(cached_in(&path, &private, 803223).unwrap().name(), Some("Private"));
+        let link = cached_in(&path, &private, 803223).unwrap().download_link();
+        assert_eq!(link.as_deref(), Some("http://example.com/Private.mp3"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+/// Fewer taps than this give too rough a tempo
+pub const MIN_TEMPO_TAPS: usize = 4;
+
+/// What a tap does while the song plays
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TapMode {
+    Off,
+    /// Collect taps to estimate the tempo from
+    Tempo,
+    /// Put a line where each tap lands
+    Place,
+    /// Tap along to the grid to measure latency
+    Calibrate,
+}
+
+/// A steady tempo fitted to taps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TapTempo {
+    pub beat_length: Duration,
+    /// Fitted time of the first tap's beat
+    pub first_beat: Duration,
+}
+
+impl TapTempo {
+    pub fn bpm(self) -> f64 {
+        60.0 / self.beat_length.as_secs_f64()
+    }
+}
+
+/// Fits a steady tempo to `taps` (at least `MIN_TEMPO_TAPS`, on every beat or with a few left
+/// out); least squares over the beat each tap is closest to, so jitter averages out
+pub fn estimate_tempo(taps: &[Duration]) -> Option<TapTempo> {
+    if taps.len() < MIN_TEMPO_TAPS {
+        return None;
+    }
+    let mut taps: Vec<f64> = taps.iter().map(Duration::as_secs_f64).collect();
+    taps.sort_by(f64::total_cmp);
+    let mut intervals: Vec<f64> = taps.windows(2).map(|pair| pair[1] - pair[0]).filter(|&i| i > 0.0).collect();
+    intervals.sort_by(f64::total_cmp);
+    // the median shrugs off the odd skipped beat
+    let rough = *intervals.get(intervals.len() / 2)?;
+
+    let beats: Vec<f64> = taps.iter().map(|tap| ((tap - taps[0]) / rough).round()).collect();
+    let count = taps.len() as f64;
+    let mean_beat = beats.iter().sum::<f64>() / count;
+    let mean_tap = taps.iter().sum::<f64>() / count;
+    let covariance: f64 = beats.iter().zip(&taps).map(|(beat, tap)| (beat - mean_beat) * (tap - mean_tap)).sum();
+    let variance: f64 = beats.iter().map(|beat| (beat - mean_beat).powi(2)).sum();
+    let beat_length = covariance / variance;
+    if !beat_length.is_finite() || beat_length <= 0.0 {
+        return None;
+    }
+    let first_beat = mean_tap - beat_length * mean_beat;
+    Some(TapTempo {
+        beat_length: Duration::from_secs_f64(beat_length),
+        first_beat: Duration::from_secs_f64(first_beat.max(0.0)),
+    })
+}
+
+/// How many seconds taps land after the beat they were aimed at (the median, so stray taps
+/// don't count); `nearest_beat` finds the beat a tap was aimed at
+pub fn measure_latency(taps: &[Duration], nearest_beat: impl Fn(Duration) -> Duration) -> Option<f64> {
+    let mut offsets: Vec<f64> = taps
+        .iter()
+        .map(|&tap| tap.as_secs_f64() - nearest_beat(tap).as_secs_f64())
+        .collect();
+    offsets.sort_by(f64::total_cmp);
+    offsets.get(offsets.len() / 2).copied()
+}
+
+/// Where a tap was aimed, with `latency` seconds taken off
+pub fn compensate(tap: Duration, latency: f64) -> Duration {
+    Duration::from_secs_f64((tap.as_secs_f64() - latency).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(times: &[f64]) -> Vec<Duration> {
+        times.iter().map(|&t| Duration::from_secs_f64(t)).collect()
+    }
+
+    #[test]
+    fn steady_taps() {
+        // 150 bpm from 0.5s, a little sloppy, with beat 4 left out
+        let taps = secs(&[0.502, 0.898, 1.301, 2.099, 2.5, 2.903]);
+        let tempo = estimate_tempo(&taps).unwrap();
+        assert!((tempo.bpm() - 150.0).abs() < 0.5, "{}", tempo.bpm());
+        assert!((tempo.first_beat.as_secs_f64() - 0.5).abs() < 0.005, "{:?}", tempo.first_beat);
+    }
+
+    #[test]
+    fn too_few_taps() {
+        assert_eq!(estimate_tempo(&secs(&[0.0, 0.5, 1.0])), None);
+        assert_eq!(estimate_tempo(&secs(&[1.0; 4])), None);
+    }
+
+    #[test]
+    fn latency_is_median_offset() {
+        // beats every half second; one stray tap
+        let nearest = |tap: Duration| Duration::from_secs_f64((tap.as_secs_f64() * 2.0).round() / 2.0);
+        let taps = secs(&[0.53, 1.028, 1.532, 1.7, 2.03]);
+        let latency = measure_latency(&taps, nearest).unwrap();
+        assert!((latency - 0.03).abs() < 1e-9, "{latency}");
+        assert_eq!(measure_latency(&[], nearest), None);
+    }
+
+    #[test]
+    fn compensation_stops_at_song_start() {
+        assert_eq!(compensate(Duration::from_millis(530), 0.03), Duration::from_millis(500));
+        assert_eq!(compensate(Duration::from_millis(10), 0.03), Duration::ZERO);
+    }
+}